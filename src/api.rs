@@ -0,0 +1,306 @@
+use anyhow::{bail as yeet, Context, Result};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+};
+use tracing::{error, info, warn};
+
+use crate::app::App;
+
+/// How long a client gets to send its whole request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long the request line and headers can be together
+const MAX_HEAD_LENGTH: u64 = 8192;
+
+/// How long the body can be
+const MAX_BODY_LENGTH: usize = 16384;
+
+/// How long a chat message can be, same as in the vanilla client
+const MAX_MESSAGE_LENGTH: usize = 256;
+
+/// How many messages `GET /chat` returns at most
+const MAX_CHAT_LIMIT: usize = 100;
+
+/// A request to the API, which is all we need from HTTP
+struct Request {
+    method: String,
+    path: String,
+    query: Option<String>,
+    headers: HashMap<String, String>,
+    body: Vec<u8>,
+}
+
+struct Response {
+    status: u16,
+    body: Value,
+}
+
+#[derive(Deserialize)]
+struct ChatRequest {
+    message: String,
+}
+
+/// When each token sent its messages in the last minute
+#[derive(Default)]
+struct RateLimits {
+    sent: Mutex<HashMap<String, VecDeque<Instant>>>,
+}
+
+impl App {
+    /// Starts the HTTP API if it's enabled
+    ///
+    /// `GET /chat` returns the recent chat, `POST /chat` with a JSON body like
+    /// `{"message": "hi"}` sends a chat message or command as the bot.
+    pub fn spawn_api(&self) {
+        let config = self.config.get();
+        if !config.api.enabled {
+            return;
+        }
+
+        let app = self.clone();
+        let addr = config.api.listen_addr;
+        tokio::spawn(async move {
+            let listener = match TcpListener::bind(addr).await {
+                Ok(listener) => listener,
+                Err(err) => {
+                    error!("Failed to start the API on {addr}: {err}");
+                    return;
+                }
+            };
+            info!("API listening on {addr}");
+
+            let limits = Arc::new(RateLimits::default());
+            loop {
+                let (stream, remote) = match listener.accept().await {
+                    Ok(accepted) => accepted,
+                    Err(err) => {
+                        error!("Failed to accept API connection: {err}");
+                        continue;
+                    }
+                };
+
+                let app = app.clone();
+                let limits = limits.clone();
+                tokio::spawn(async move {
+                    let handled =
+                        tokio::time::timeout(REQUEST_TIMEOUT, app.handle_api(stream, &limits));
+                    match handled.await {
+                        Ok(Ok(())) => {}
+                        Ok(Err(err)) => warn!("Bad API request from {remote}: {err}"),
+                        Err(_) => warn!("API request from {remote} took too long"),
+                    }
+                });
+            }
+        });
+    }
+
+    async fn handle_api(&self, stream: TcpStream, limits: &RateLimits) -> Result<()> {
+        let mut reader = BufReader::new(stream);
+        let response = match read_request(&mut reader).await {
+            Ok(request) => self.respond(request, limits),
+            Err(err) => {
+                warn!("Couldn't read an API request: {err}");
+                error_response(400, "Bad request")
+            }
+        };
+
+        let body = response.body.to_string();
+        let head = format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            response.status,
+            reason(response.status),
+            body.len()
+        );
+        let stream = reader.get_mut();
+        stream.write_all(head.as_bytes()).await?;
+        stream.write_all(body.as_bytes()).await?;
+        stream.shutdown().await?;
+        Ok(())
+    }
+
+    fn respond(&self, request: Request, limits: &RateLimits) -> Response {
+        let config = self.config.get();
+        let token = request
+            .headers
+            .get("authorization")
+            .and_then(|value| value.strip_prefix("Bearer "));
+        let Some(token) = token.filter(|token| is_known_token(&config.api.tokens, token)) else {
+            return error_response(401, "Missing or unknown token");
+        };
+
+        match (request.method.as_str(), request.path.as_str()) {
+            ("GET", "/chat") => {
+                let limit = request
+                    .query
+                    .as_deref()
+                    .and_then(|query| {
+                        query
+                            .split('&')
+                            .find_map(|pair| pair.strip_prefix("limit="))
+                    })
+                    .and_then(|limit| limit.parse().ok())
+                    .unwrap_or(MAX_CHAT_LIMIT)
+                    .min(MAX_CHAT_LIMIT);
+                let messages = self.chat_log.search(limit, |_| true);
+                Response {
+                    status: 200,
+                    body: json!({ "messages": messages }),
+                }
+            }
+            ("POST", "/chat") => {
+                let Ok(chat) = serde_json::from_slice::<ChatRequest>(&request.body) else {
+                    return error_response(400, "Expected a body like {\"message\": \"...\"}");
+                };
+                if chat.message.is_empty() || chat.message.chars().count() > MAX_MESSAGE_LENGTH {
+                    return error_response(400, "The message has to be 1 to 256 characters");
+                }
+                if chat.message.chars().any(char::is_control) {
+                    return error_response(400, "The message can't have control characters");
+                }
+                if !limits.allow(token, config.api.messages_per_minute) {
+                    return error_response(429, "Sending too many messages");
+                }
+
+                match self.send_chat(&chat.message) {
+                    Ok(()) => {
+                        info!("Sent a message through the API: {}", chat.message);
+                        Response {
+                            status: 200,
+                            body: json!({ "sent": true }),
+                        }
+                    }
+                    Err(err) => error_response(503, &err.to_string()),
+                }
+            }
+            (_, "/chat") => error_response(405, "Only GET and POST work here"),
+            _ => error_response(404, "Not found"),
+        }
+    }
+}
+
+impl RateLimits {
+    /// Counts a message towards the token's limit if it's still within it
+    fn allow(&self, token: &str, per_minute: u32) -> bool {
+        let now = Instant::now();
+        let mut sent = self.sent.lock().unwrap();
+        let times = sent.entry(token.to_string()).or_default();
+        while times
+            .front()
+            .map_or(false, |time| now - *time >= Duration::from_secs(60))
+        {
+            times.pop_front();
+        }
+
+        if times.len() >= per_minute as usize {
+            return false;
+        }
+        times.push_back(now);
+        true
+    }
+}
+
+async fn read_request(reader: &mut BufReader<TcpStream>) -> Result<Request> {
+    let mut head = (&mut *reader).take(MAX_HEAD_LENGTH);
+
+    let mut line = String::new();
+    head.read_line(&mut line).await?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        yeet!("Invalid request line");
+    };
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path.to_string(), Some(query.to_string())),
+        None => (target.to_string(), None),
+    };
+    let method = method.to_string();
+
+    let mut headers = HashMap::new();
+    loop {
+        line.clear();
+        if head.read_line(&mut line).await? == 0 {
+            yeet!("The headers are too long or cut off");
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        let (name, value) = line.split_once(':').context("Invalid header")?;
+        headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+    }
+
+    let length = match headers.get("content-length") {
+        Some(length) => length.parse().context("Invalid Content-Length")?,
+        None => 0,
+    };
+    if length > MAX_BODY_LENGTH {
+        yeet!("The body is too long");
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).await?;
+
+    Ok(Request {
+        method,
+        path,
+        query,
+        headers,
+        body,
+    })
+}
+
+/// Looks for a token without giving away through timing how much of it was
+/// right
+fn is_known_token(tokens: &[String], token: &str) -> bool {
+    let token = Sha256::digest(token);
+    tokens.iter().fold(false, |known, candidate| {
+        let candidate = Sha256::digest(candidate);
+        let difference = token
+            .iter()
+            .zip(candidate.iter())
+            .fold(0, |difference, (a, b)| difference | (a ^ b));
+        known | (difference == 0)
+    })
+}
+
+fn error_response(status: u16, message: &str) -> Response {
+    Response {
+        status,
+        body: json!({ "error": message }),
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        429 => "Too Many Requests",
+        503 => "Service Unavailable",
+        _ => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn knows_tokens() {
+        let tokens = vec!["first".to_string(), "second".to_string()];
+        assert!(is_known_token(&tokens, "first"));
+        assert!(is_known_token(&tokens, "second"));
+        assert!(!is_known_token(&tokens, "firs"));
+        assert!(!is_known_token(&tokens, "second "));
+        assert!(!is_known_token(&[], "first"));
+    }
+}
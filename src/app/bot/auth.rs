@@ -0,0 +1,76 @@
+use anyhow::Result;
+use azalea_client::Account;
+use chrono::Utc;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+use crate::{app::App, config::AccountKind, events::EventKind, store::AuthStatus};
+
+/// Logging in with cached tokens never takes this long, so someone probably
+/// has to authenticate in the browser
+const AUTH_PROMPT_DELAY: Duration = Duration::from_secs(5);
+
+/// How long a login is reused before the tokens are checked again
+const LOGIN_REUSE: Duration = Duration::from_secs(60 * 60);
+
+/// The last successful login
+#[derive(Debug)]
+pub(super) struct LoggedIn {
+    /// The account from the config, which may have changed since
+    account: String,
+    logged_in: Account,
+    time: Instant,
+}
+
+impl App {
+    /// Logs into the account from the config, letting everyone know if that
+    /// needs a human. Everyone asking at the same time shares one login.
+    pub async fn authenticate(&self) -> Result<Account> {
+        let config = self.config.get();
+        let account = config.account.clone();
+        if config.account_kind == AccountKind::Offline {
+            return Ok(Account::offline(&account));
+        }
+
+        // Whoever comes second waits for the first login to finish
+        let mut last = self.bot.logged_in.lock().await;
+        if let Some(cached) = last.as_ref() {
+            if cached.account == account && cached.time.elapsed() < LOGIN_REUSE {
+                return Ok(cached.logged_in.clone());
+            }
+        }
+
+        let login = Account::microsoft(&account);
+        tokio::pin!(login);
+
+        let quick = tokio::select! {
+            result = &mut login => Some(result),
+            _ = tokio::time::sleep(AUTH_PROMPT_DELAY) => None,
+        };
+        let logged_in = match quick {
+            Some(result) => result?,
+            None => {
+                warn!("Still logging into {account}, it probably has to be authenticated");
+                self.events.emit(EventKind::AuthPrompt {
+                    account: account.clone(),
+                });
+                login.await?
+            }
+        };
+
+        self.store.update(|state| {
+            state.auth = Some(AuthStatus {
+                account: account.clone(),
+                username: logged_in.username.clone(),
+                uuid: logged_in.uuid,
+                time: Utc::now(),
+            })
+        });
+        *last = Some(LoggedIn {
+            account: account.clone(),
+            logged_in: logged_in.clone(),
+            time: Instant::now(),
+        });
+        Ok(logged_in)
+    }
+}
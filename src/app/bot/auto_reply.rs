@@ -0,0 +1,88 @@
+use anyhow::Result;
+use regex::Regex;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::{app::App, chat_log::ChatMessage, events::EventKind};
+
+/// How many whispers are kept for the player at most
+const MAX_BUFFERED_WHISPERS: usize = 100;
+
+impl App {
+    /// Compiles the pattern that matches whispers on the current server
+    pub(super) fn whisper_pattern(&self) -> Result<Regex> {
+        let config = self.config.get();
+        let auto_reply = &config.auto_reply;
+        let pattern = auto_reply
+            .patterns
+            .get(&self.server_addr().to_string())
+            .unwrap_or(&auto_reply.default_pattern);
+        Ok(Regex::new(pattern)?)
+    }
+
+    /// Announces a chat message if it's a whisper, then buffers it for the
+    /// player and replies to it if auto replies are on, unless we've replied
+    /// to the same player recently
+    pub(super) fn handle_whisper(&self, pattern: &Regex, message: &ChatMessage) {
+        let Some(sender) = pattern
+            .captures(&message.message)
+            .and_then(|captures| captures.name("name"))
+            .map(|name| name.as_str().to_string())
+        else {
+            return;
+        };
+
+        // Don't start a conversation with ourselves
+        let config = self.config.get();
+        if sender == config.player {
+            return;
+        }
+
+        info!("{sender} whispered to the bot");
+        self.events.emit(EventKind::Whisper {
+            from: sender.clone(),
+            message: message.message.clone(),
+        });
+
+        {
+            let mut whispers = self.bot.whispers.lock().unwrap();
+            if whispers.len() >= MAX_BUFFERED_WHISPERS {
+                whispers.pop_front();
+            }
+            whispers.push_back(message.clone());
+        }
+
+        let auto_reply = &config.auto_reply;
+        if !auto_reply.enabled {
+            return;
+        }
+
+        let cooldown = Duration::from_secs(auto_reply.cooldown);
+        {
+            let mut replied = self.bot.replied.lock().unwrap();
+            // Whoever is past their cooldown would get a reply anyway
+            replied.retain(|_, last| last.elapsed() < cooldown);
+            if let Some(last) = replied.get(&sender) {
+                if last.elapsed() < cooldown {
+                    return;
+                }
+            }
+            replied.insert(sender.clone(), Instant::now());
+        }
+
+        let away = self.bot.away_for().unwrap_or_default();
+        let reply = auto_reply.message.replace("{away}", &format_duration(away));
+        if let Err(err) = self.send_chat(&format!("/msg {sender} {reply}")) {
+            warn!("Failed to reply to {sender}: {err}");
+        }
+    }
+}
+
+/// Formats a duration like `2h 5m`
+fn format_duration(duration: Duration) -> String {
+    let minutes = duration.as_secs() / 60;
+    match (minutes / 60, minutes % 60) {
+        (0, minutes) => format!("{minutes}m"),
+        (hours, minutes) => format!("{hours}h {minutes}m"),
+    }
+}
@@ -0,0 +1,725 @@
+use azalea_core::{ChunkPos, GameType, PositionDelta8, ResourceLocation, Slot, Vec3};
+use azalea_protocol::packets::game::{
+    clientbound_add_player_packet::ClientboundAddPlayerPacket,
+    clientbound_boss_event_packet::{self, ClientboundBossEventPacket},
+    clientbound_container_set_content_packet::ClientboundContainerSetContentPacket,
+    clientbound_game_event_packet::EventType,
+    clientbound_level_chunk_with_light_packet::ClientboundLevelChunkWithLightPacket,
+    clientbound_login_packet::ClientboundLoginPacket,
+    clientbound_player_info_remove_packet::ClientboundPlayerInfoRemovePacket,
+    clientbound_player_info_update_packet::{
+        ActionEnumSet, ClientboundPlayerInfoUpdatePacket, PlayerInfoEntry,
+    },
+    clientbound_player_position_packet::{ClientboundPlayerPositionPacket, RelativeArguments},
+    clientbound_respawn_packet::ClientboundRespawnPacket,
+    clientbound_set_objective_packet::{self, ClientboundSetObjectivePacket},
+    clientbound_set_player_team_packet::{self, ClientboundSetPlayerTeamPacket},
+    clientbound_teleport_entity_packet::ClientboundTeleportEntityPacket,
+    ClientboundGamePacket, ServerboundGamePacket,
+};
+use azalea_registry::Item;
+use std::{
+    collections::{HashMap, HashSet},
+    mem::Discriminant,
+};
+use uuid::Uuid;
+
+/// The teleport id used when putting a client where the bot is. The server
+/// doesn't know about it, so the client's confirmation must not be forwarded.
+pub const REPLAY_TELEPORT_ID: u32 = 0x7fff_ffff;
+
+/// The entity id spectators see themselves as, far away from the ids servers
+/// hand out
+pub const SPECTATOR_ENTITY_ID: u32 = 0x7fff_fffe;
+
+/// How many entity updates are kept per entity at most
+const MAX_ENTITY_UPDATES: usize = 32;
+
+/// How many block changes are kept per chunk at most. Farms flip the same
+/// blocks over and over, so only the latest change of each block is kept.
+const MAX_CHUNK_UPDATES: usize = 256;
+
+/// The id of the player's own inventory window
+const INVENTORY_CONTAINER: i8 = 0;
+
+/// The id used by servers to set a slot in the player's inventory directly
+const INVENTORY_CONTAINER_DIRECT: i8 = -2;
+
+/// Everything the bot knows about the world, built up from the packets the
+/// server sends. It's enough to put a client into the world exactly as the
+/// bot sees it.
+#[derive(Debug, Default)]
+pub struct WorldCache {
+    login: Option<ClientboundLoginPacket>,
+
+    /// The latest packet of each kind that only matters in its latest version,
+    /// in the order they were first seen
+    latest: Vec<ClientboundGamePacket>,
+
+    tab_list: HashMap<Uuid, PlayerInfoEntry>,
+    inventory: Option<ClientboundContainerSetContentPacket>,
+    chunks: HashMap<ChunkPos, CachedChunk>,
+    entities: HashMap<u32, CachedEntity>,
+
+    /// What's shown on the client's screen outside of the world, which only
+    /// goes away when the server says so
+    boss_bars: HashSet<Uuid>,
+    objectives: HashSet<String>,
+    teams: HashSet<String>,
+
+    pub health: Option<f32>,
+
+    /// The health before the last health update
+    pub previous_health: Option<f32>,
+
+    /// Whether the server has told us where the bot is yet
+    positioned: bool,
+
+    pub position: Vec3,
+    pub y_rot: f32,
+    pub x_rot: f32,
+    pub on_ground: bool,
+}
+
+/// Another player in render distance
+#[derive(Debug, Clone)]
+pub struct TrackedPlayer {
+    pub uuid: Uuid,
+    pub position: Vec3,
+}
+
+#[derive(Debug)]
+struct CachedChunk {
+    packet: ClientboundLevelChunkWithLightPacket,
+
+    /// Block changes since the chunk was sent
+    updates: Vec<ClientboundGamePacket>,
+}
+
+#[derive(Debug)]
+struct CachedEntity {
+    spawn: ClientboundGamePacket,
+    position: Vec3,
+    y_rot: i8,
+    x_rot: i8,
+
+    /// Metadata and equipment changes since the entity was spawned
+    updates: Vec<ClientboundGamePacket>,
+}
+
+impl WorldCache {
+    /// Whether the server has let us into the world yet
+    pub fn is_ready(&self) -> bool {
+        self.login.is_some()
+    }
+
+    /// The bot's own entity id
+    pub fn entity_id(&self) -> Option<u32> {
+        self.login.as_ref().map(|login| login.player_id)
+    }
+
+    /// Updates the cache with a packet from the server
+    pub fn update(&mut self, packet: &ClientboundGamePacket) {
+        match packet {
+            ClientboundGamePacket::Login(packet) => {
+                *self = Self {
+                    login: Some(packet.clone()),
+                    ..Self::default()
+                };
+            }
+            ClientboundGamePacket::Respawn(packet) => {
+                if let Some(login) = &mut self.login {
+                    login.dimension_type = packet.dimension_type.clone();
+                    login.dimension = packet.dimension.clone();
+                    login.game_type = packet.player_game_type;
+                }
+                self.chunks.clear();
+                self.entities.clear();
+            }
+            ClientboundGamePacket::UpdateEnabledFeatures(_)
+            | ClientboundGamePacket::UpdateTags(_)
+            | ClientboundGamePacket::UpdateRecipes(_)
+            | ClientboundGamePacket::Commands(_)
+            | ClientboundGamePacket::ChangeDifficulty(_)
+            | ClientboundGamePacket::ServerData(_)
+            | ClientboundGamePacket::PlayerAbilities(_)
+            | ClientboundGamePacket::SetCarriedItem(_)
+            | ClientboundGamePacket::InitializeBorder(_)
+            | ClientboundGamePacket::SetDefaultSpawnPosition(_)
+            | ClientboundGamePacket::SetTime(_)
+            | ClientboundGamePacket::SetChunkCacheCenter(_)
+            | ClientboundGamePacket::SetChunkCacheRadius(_)
+            | ClientboundGamePacket::SetSimulationDistance(_)
+            | ClientboundGamePacket::SetExperience(_) => {
+                self.keep_latest(packet);
+            }
+            ClientboundGamePacket::SetHealth(update) => {
+                self.previous_health = self.health;
+                self.health = Some(update.health);
+                self.keep_latest(packet);
+            }
+            ClientboundGamePacket::GameEvent(packet) => {
+                if let (EventType::ChangeGameMode, Some(login)) = (&packet.event, &mut self.login) {
+                    if let Some(game_type) = GameType::from_id(packet.param as u8) {
+                        login.game_type = game_type;
+                    }
+                }
+            }
+            ClientboundGamePacket::PlayerInfoUpdate(packet) => {
+                self.update_tab_list(packet);
+            }
+            ClientboundGamePacket::PlayerInfoRemove(packet) => {
+                for uuid in &packet.profile_ids {
+                    self.tab_list.remove(uuid);
+                }
+            }
+            ClientboundGamePacket::BossEvent(packet) => match packet.operation {
+                clientbound_boss_event_packet::Operation::Remove => {
+                    self.boss_bars.remove(&packet.id);
+                }
+                _ => {
+                    self.boss_bars.insert(packet.id);
+                }
+            },
+            ClientboundGamePacket::SetObjective(packet) => match packet.method {
+                clientbound_set_objective_packet::Method::Remove => {
+                    self.objectives.remove(&packet.objective_name);
+                }
+                _ => {
+                    self.objectives.insert(packet.objective_name.clone());
+                }
+            },
+            ClientboundGamePacket::SetPlayerTeam(packet) => match packet.method {
+                clientbound_set_player_team_packet::Method::Remove => {
+                    self.teams.remove(&packet.name);
+                }
+                _ => {
+                    self.teams.insert(packet.name.clone());
+                }
+            },
+            ClientboundGamePacket::ContainerSetContent(packet) => {
+                if packet.container_id as i8 == INVENTORY_CONTAINER {
+                    self.inventory = Some(packet.clone());
+                }
+            }
+            ClientboundGamePacket::ContainerSetSlot(packet) => {
+                if packet.container_id == INVENTORY_CONTAINER
+                    || packet.container_id == INVENTORY_CONTAINER_DIRECT
+                {
+                    if let Some(slot) = self
+                        .inventory
+                        .as_mut()
+                        .and_then(|inventory| inventory.items.get_mut(packet.slot as usize))
+                    {
+                        *slot = packet.item_stack.clone();
+                    }
+                }
+            }
+            ClientboundGamePacket::LevelChunkWithLight(packet) => {
+                self.chunks.insert(
+                    ChunkPos::new(packet.x, packet.z),
+                    CachedChunk {
+                        packet: packet.clone(),
+                        updates: vec![],
+                    },
+                );
+            }
+            ClientboundGamePacket::ForgetLevelChunk(packet) => {
+                self.chunks.remove(&packet.pos);
+            }
+            ClientboundGamePacket::BlockUpdate(update) => {
+                self.add_chunk_update(ChunkPos::from(&update.pos), packet);
+            }
+            ClientboundGamePacket::BlockEntityData(update) => {
+                self.add_chunk_update(ChunkPos::from(&update.pos), packet);
+            }
+            ClientboundGamePacket::SectionBlocksUpdate(update) => {
+                let pos = ChunkPos::new(update.section_pos.x, update.section_pos.z);
+                self.add_chunk_update(pos, packet);
+            }
+            ClientboundGamePacket::AddPlayer(spawn) => {
+                self.entities.insert(
+                    spawn.id,
+                    CachedEntity {
+                        spawn: packet.clone(),
+                        position: spawn.position,
+                        y_rot: spawn.y_rot,
+                        x_rot: spawn.x_rot,
+                        updates: vec![],
+                    },
+                );
+            }
+            ClientboundGamePacket::AddEntity(spawn) => {
+                self.entities.insert(
+                    spawn.id,
+                    CachedEntity {
+                        spawn: packet.clone(),
+                        position: spawn.position,
+                        y_rot: spawn.y_rot,
+                        x_rot: spawn.x_rot,
+                        updates: vec![],
+                    },
+                );
+            }
+            ClientboundGamePacket::TeleportEntity(packet) => {
+                if let Some(entity) = self.entities.get_mut(&packet.id) {
+                    entity.position = packet.position;
+                    entity.y_rot = packet.y_rot;
+                    entity.x_rot = packet.x_rot;
+                }
+            }
+            ClientboundGamePacket::MoveEntityPos(packet) => {
+                if let Some(entity) = self.entities.get_mut(&packet.entity_id) {
+                    move_by(&mut entity.position, &packet.delta);
+                }
+            }
+            ClientboundGamePacket::MoveEntityPosRot(packet) => {
+                if let Some(entity) = self.entities.get_mut(&packet.entity_id) {
+                    move_by(&mut entity.position, &packet.delta);
+                    entity.y_rot = packet.y_rot;
+                    entity.x_rot = packet.x_rot;
+                }
+            }
+            ClientboundGamePacket::MoveEntityRot(packet) => {
+                if let Some(entity) = self.entities.get_mut(&packet.entity_id) {
+                    entity.y_rot = packet.y_rot;
+                    entity.x_rot = packet.x_rot;
+                }
+            }
+            ClientboundGamePacket::SetEntityData(update) => {
+                self.add_entity_update(update.id, packet);
+            }
+            ClientboundGamePacket::SetEquipment(update) => {
+                self.add_entity_update(update.entity, packet);
+            }
+            ClientboundGamePacket::RemoveEntities(packet) => {
+                for id in &packet.entity_ids {
+                    self.entities.remove(id);
+                }
+            }
+            ClientboundGamePacket::PlayerPosition(packet) => {
+                let relative = &packet.relative_arguments;
+                let old = self.position;
+                self.position = Vec3 {
+                    x: packet.x + if relative.x { old.x } else { 0.0 },
+                    y: packet.y + if relative.y { old.y } else { 0.0 },
+                    z: packet.z + if relative.z { old.z } else { 0.0 },
+                };
+                self.y_rot = packet.y_rot + if relative.y_rot { self.y_rot } else { 0.0 };
+                self.x_rot = packet.x_rot + if relative.x_rot { self.x_rot } else { 0.0 };
+                self.positioned = true;
+            }
+            _ => {}
+        }
+    }
+
+    /// Updates the bot's position with a movement packet from the player
+    /// controlling it, returning whether it was one
+    pub fn apply_movement(&mut self, packet: &ServerboundGamePacket) -> bool {
+        match packet {
+            ServerboundGamePacket::MovePlayerPos(packet) => {
+                self.position = Vec3 {
+                    x: packet.x,
+                    y: packet.y,
+                    z: packet.z,
+                };
+                self.on_ground = packet.on_ground;
+            }
+            ServerboundGamePacket::MovePlayerPosRot(packet) => {
+                self.position = Vec3 {
+                    x: packet.x,
+                    y: packet.y,
+                    z: packet.z,
+                };
+                self.y_rot = packet.y_rot;
+                self.x_rot = packet.x_rot;
+                self.on_ground = packet.on_ground;
+            }
+            ServerboundGamePacket::MovePlayerRot(packet) => {
+                self.y_rot = packet.y_rot;
+                self.x_rot = packet.x_rot;
+                self.on_ground = packet.on_ground;
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Where the bot is, once the server has told us
+    pub fn known_position(&self) -> Option<Vec3> {
+        self.positioned.then_some(self.position)
+    }
+
+    /// Another player in render distance by entity id
+    pub fn player(&self, id: u32) -> Option<TrackedPlayer> {
+        let entity = self.entities.get(&id)?;
+        match &entity.spawn {
+            ClientboundGamePacket::AddPlayer(spawn) => Some(TrackedPlayer {
+                uuid: spawn.uuid,
+                position: entity.position,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Every other player in render distance
+    pub fn players(&self) -> impl Iterator<Item = TrackedPlayer> + '_ {
+        self.entities.keys().filter_map(|id| self.player(*id))
+    }
+
+    /// The name of a player, if they're in the tab list yet. Servers usually
+    /// send the entry first, but not always.
+    pub fn player_name(&self, uuid: &Uuid) -> Option<String> {
+        self.tab_list
+            .get(uuid)
+            .map(|entry| entry.profile.name.clone())
+            .filter(|name| !name.is_empty())
+    }
+
+    /// Other players that are within `radius` blocks of the bot
+    pub fn players_within(&self, radius: f64) -> impl Iterator<Item = TrackedPlayer> + '_ {
+        let position = self.known_position();
+        self.players().filter(move |player| {
+            position.map_or(false, |position| {
+                distance(&position, &player.position) <= radius
+            })
+        })
+    }
+
+    /// How many totems of undying are in the inventory, if it is known yet
+    pub fn totem_count(&self) -> Option<u32> {
+        let inventory = self.inventory.as_ref()?;
+        let count = inventory
+            .items
+            .iter()
+            .filter_map(|slot| match slot {
+                Slot::Present(data) if data.id == Item::TotemOfUndying => Some(data.count as u32),
+                _ => None,
+            })
+            .sum();
+        Some(count)
+    }
+
+    /// Whether the health went down with the last health update
+    pub fn took_damage(&self) -> bool {
+        matches!(
+            (self.previous_health, self.health),
+            (Some(previous), Some(current)) if current < previous
+        )
+    }
+
+    /// The packets that put a client into the world as the bot
+    pub fn replay(&self) -> Vec<ClientboundGamePacket> {
+        let Some(login) = &self.login else {
+            return vec![];
+        };
+
+        let mut packets = vec![login.clone().get()];
+        packets.extend(self.world_packets());
+        if let Some(inventory) = &self.inventory {
+            packets.push(inventory.clone().get());
+        }
+        packets.push(self.position_packet());
+        packets
+    }
+
+    /// The packets that put a spectator into the world, watching the bot
+    pub fn spectator_replay(&self, bot_uuid: Uuid) -> Vec<ClientboundGamePacket> {
+        let Some(login) = &self.login else {
+            return vec![];
+        };
+
+        let mut login = login.clone();
+        login.player_id = SPECTATOR_ENTITY_ID;
+        login.game_type = GameType::Spectator;
+
+        let mut packets = vec![login.get()];
+        packets.extend(
+            self.world_packets()
+                .into_iter()
+                .filter(|packet| !is_personal(packet)),
+        );
+        packets.push(self.position_packet());
+        packets.extend(self.bot_spawn_packet(bot_uuid));
+        packets
+    }
+
+    /// What a spectator sees of a packet the server sent to the bot
+    pub fn spectator_view(
+        &self,
+        packet: &ClientboundGamePacket,
+        bot_uuid: Uuid,
+    ) -> Vec<ClientboundGamePacket> {
+        match packet {
+            ClientboundGamePacket::Login(_) => vec![],
+            ClientboundGamePacket::Respawn(packet) => {
+                let mut packet = packet.clone();
+                packet.player_game_type = GameType::Spectator;
+                let mut packets = vec![packet.get()];
+                packets.extend(self.bot_spawn_packet(bot_uuid));
+                packets
+            }
+            // The server moved the bot, not the spectator
+            ClientboundGamePacket::PlayerPosition(_) => {
+                self.bot_teleport_packet().into_iter().collect()
+            }
+            packet if is_personal(packet) => vec![],
+            packet => vec![packet.clone()],
+        }
+    }
+
+    /// The packets that clear out what's left on a client's screen from this
+    /// server before it's put into another one
+    pub fn leftovers(&self) -> Vec<ClientboundGamePacket> {
+        let mut packets = vec![ClientboundPlayerInfoRemovePacket {
+            profile_ids: self.tab_list.keys().copied().collect(),
+        }
+        .get()];
+        packets.extend(self.boss_bars.iter().map(|id| {
+            ClientboundBossEventPacket {
+                id: *id,
+                operation: clientbound_boss_event_packet::Operation::Remove,
+            }
+            .get()
+        }));
+        packets.extend(self.objectives.iter().map(|name| {
+            ClientboundSetObjectivePacket {
+                objective_name: name.clone(),
+                method: clientbound_set_objective_packet::Method::Remove,
+            }
+            .get()
+        }));
+        packets.extend(self.teams.iter().map(|name| {
+            ClientboundSetPlayerTeamPacket {
+                name: name.clone(),
+                method: clientbound_set_player_team_packet::Method::Remove,
+            }
+            .get()
+        }));
+        packets
+    }
+
+    /// Moves the bot's player entity to where the bot is for spectators
+    pub fn bot_teleport_packet(&self) -> Option<ClientboundGamePacket> {
+        Some(
+            ClientboundTeleportEntityPacket {
+                id: self.entity_id()?,
+                position: self.position,
+                y_rot: angle_to_byte(self.y_rot),
+                x_rot: angle_to_byte(self.x_rot),
+                on_ground: self.on_ground,
+            }
+            .get(),
+        )
+    }
+
+    /// Spawns the bot as a player entity for spectators
+    pub fn bot_spawn_packet(&self, bot_uuid: Uuid) -> Option<ClientboundGamePacket> {
+        Some(
+            ClientboundAddPlayerPacket {
+                id: self.entity_id()?,
+                uuid: bot_uuid,
+                position: self.position,
+                y_rot: angle_to_byte(self.y_rot),
+                x_rot: angle_to_byte(self.x_rot),
+            }
+            .get(),
+        )
+    }
+
+    /// Puts a client where the bot is
+    fn position_packet(&self) -> ClientboundGamePacket {
+        ClientboundPlayerPositionPacket {
+            x: self.position.x,
+            y: self.position.y,
+            z: self.position.z,
+            y_rot: self.y_rot,
+            x_rot: self.x_rot,
+            relative_arguments: RelativeArguments {
+                x: false,
+                y: false,
+                z: false,
+                y_rot: false,
+                x_rot: false,
+            },
+            id: REPLAY_TELEPORT_ID,
+            dismount_vehicle: false,
+        }
+        .get()
+    }
+
+    /// Everything in the world apart from the login, the inventory and where
+    /// the bot is
+    fn world_packets(&self) -> Vec<ClientboundGamePacket> {
+        let mut packets = self.latest.clone();
+
+        packets.push(
+            ClientboundPlayerInfoUpdatePacket {
+                actions: ActionEnumSet {
+                    add_player: true,
+                    initialize_chat: true,
+                    update_game_mode: true,
+                    update_listed: true,
+                    update_latency: true,
+                    update_display_name: true,
+                },
+                entries: self.tab_list.values().cloned().collect(),
+            }
+            .get(),
+        );
+
+        for chunk in self.chunks.values() {
+            packets.push(chunk.packet.clone().get());
+            packets.extend(chunk.updates.iter().cloned());
+        }
+
+        for (id, entity) in &self.entities {
+            packets.push(entity.spawn.clone());
+            packets.push(
+                ClientboundTeleportEntityPacket {
+                    id: *id,
+                    position: entity.position,
+                    y_rot: entity.y_rot,
+                    x_rot: entity.x_rot,
+                    on_ground: true,
+                }
+                .get(),
+            );
+            packets.extend(entity.updates.iter().cloned());
+        }
+
+        packets
+    }
+
+    fn update_tab_list(&mut self, packet: &ClientboundPlayerInfoUpdatePacket) {
+        let actions = &packet.actions;
+        for entry in &packet.entries {
+            if actions.add_player {
+                self.tab_list.insert(entry.profile.uuid, entry.clone());
+                continue;
+            }
+
+            let Some(cached) = self.tab_list.get_mut(&entry.profile.uuid) else {
+                continue;
+            };
+            if actions.initialize_chat {
+                cached.chat_session = entry.chat_session.clone();
+            }
+            if actions.update_game_mode {
+                cached.game_mode = entry.game_mode;
+            }
+            if actions.update_listed {
+                cached.listed = entry.listed;
+            }
+            if actions.update_latency {
+                cached.latency = entry.latency;
+            }
+            if actions.update_display_name {
+                cached.display_name = entry.display_name.clone();
+            }
+        }
+    }
+
+    /// Keeps a packet that only matters in its latest version
+    fn keep_latest(&mut self, packet: &ClientboundGamePacket) {
+        let kind = std::mem::discriminant(packet);
+        match self.latest.iter_mut().find(|latest| is_kind(latest, kind)) {
+            Some(latest) => *latest = packet.clone(),
+            None => self.latest.push(packet.clone()),
+        }
+    }
+
+    fn add_chunk_update(&mut self, pos: ChunkPos, packet: &ClientboundGamePacket) {
+        if let Some(chunk) = self.chunks.get_mut(&pos) {
+            if let ClientboundGamePacket::BlockUpdate(update) = packet {
+                chunk.updates.retain(|old| {
+                    !matches!(old, ClientboundGamePacket::BlockUpdate(old) if old.pos == update.pos)
+                });
+            }
+            if chunk.updates.len() >= MAX_CHUNK_UPDATES {
+                chunk.updates.remove(0);
+            }
+            chunk.updates.push(packet.clone());
+        }
+    }
+
+    fn add_entity_update(&mut self, id: u32, packet: &ClientboundGamePacket) {
+        if let Some(entity) = self.entities.get_mut(&id) {
+            if entity.updates.len() >= MAX_ENTITY_UPDATES {
+                entity.updates.remove(0);
+            }
+            entity.updates.push(packet.clone());
+        }
+    }
+}
+
+/// Respawns a client in another dimension and then the one it logged into, so
+/// it throws away the world it had from the last server like Velocity does
+pub fn dimension_swap(login: &ClientboundLoginPacket) -> Vec<ClientboundGamePacket> {
+    let elsewhere = login
+        .levels
+        .iter()
+        .find(|level| **level != login.dimension)
+        .cloned()
+        .unwrap_or_else(|| ResourceLocation::new("gooberproxy:elsewhere"));
+
+    [elsewhere, login.dimension.clone()]
+        .into_iter()
+        .map(|dimension| {
+            ClientboundRespawnPacket {
+                dimension_type: login.dimension_type.clone(),
+                dimension,
+                seed: login.seed,
+                player_game_type: login.game_type,
+                previous_player_game_type: login.previous_game_type,
+                is_debug: login.is_debug,
+                is_flat: login.is_flat,
+                data_to_keep: 0,
+                last_death_location: login.last_death_location.clone(),
+            }
+            .get()
+        })
+        .collect()
+}
+
+/// Whether a packet is about the bot itself and would confuse a spectator
+fn is_personal(packet: &ClientboundGamePacket) -> bool {
+    match packet {
+        ClientboundGamePacket::GameEvent(packet) => {
+            matches!(packet.event, EventType::ChangeGameMode)
+        }
+        ClientboundGamePacket::SetHealth(_)
+        | ClientboundGamePacket::SetExperience(_)
+        | ClientboundGamePacket::PlayerAbilities(_)
+        | ClientboundGamePacket::SetCarriedItem(_)
+        | ClientboundGamePacket::ContainerSetContent(_)
+        | ClientboundGamePacket::ContainerSetSlot(_)
+        | ClientboundGamePacket::ContainerSetData(_)
+        | ClientboundGamePacket::OpenScreen(_)
+        | ClientboundGamePacket::ContainerClose(_)
+        | ClientboundGamePacket::PlayerCombatKill(_)
+        | ClientboundGamePacket::SetCamera(_)
+        | ClientboundGamePacket::PlayerLookAt(_) => true,
+        _ => false,
+    }
+}
+
+fn is_kind(packet: &ClientboundGamePacket, kind: Discriminant<ClientboundGamePacket>) -> bool {
+    std::mem::discriminant(packet) == kind
+}
+
+/// Applies a relative entity movement (in 1/4096ths of a block)
+fn move_by(position: &mut Vec3, delta: &PositionDelta8) {
+    position.x += delta.xa as f64 / 4096.0;
+    position.y += delta.ya as f64 / 4096.0;
+    position.z += delta.za as f64 / 4096.0;
+}
+
+fn distance(a: &Vec3, b: &Vec3) -> f64 {
+    ((a.x - b.x).powi(2) + (a.y - b.y).powi(2) + (a.z - b.z).powi(2)).sqrt()
+}
+
+/// Converts an angle in degrees to the 1/256ths of a turn entity packets use
+fn angle_to_byte(degrees: f32) -> i8 {
+    (degrees.rem_euclid(360.0) / 360.0 * 256.0) as u8 as i8
+}
@@ -0,0 +1,181 @@
+use anyhow::{bail as yeet, Result};
+use azalea_core::FixedBitSet;
+use azalea_protocol::packets::game::{
+    serverbound_chat_ack_packet::ServerboundChatAckPacket,
+    serverbound_chat_command_packet::ServerboundChatCommandPacket,
+    serverbound_chat_packet::{LastSeenMessagesUpdate, ServerboundChatPacket},
+    ClientboundGamePacket, ServerboundGamePacket,
+};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::app::{bot::SessionState, App};
+
+/// How many of the most recent signed messages the server wants us to have
+/// seen, like the vanilla client
+const LAST_SEEN_COUNT: usize = 20;
+
+/// How many messages can go unacknowledged before we acknowledge them on our
+/// own, like the vanilla client
+const ACK_THRESHOLD: u32 = 64;
+
+/// Keeps track of the signed messages the bot has seen, so the server accepts
+/// what it says and doesn't kick it for ignoring chat
+#[derive(Debug, Default)]
+pub(super) struct ChatAcks {
+    /// Which of the last messages were signed, oldest first
+    seen: [bool; LAST_SEEN_COUNT],
+
+    /// Where the next message goes in `seen`
+    next: usize,
+
+    /// How many messages came in since the last acknowledgement
+    offset: u32,
+}
+
+impl ChatAcks {
+    /// Remembers a signed message the server sent
+    fn add(&mut self) {
+        self.seen[self.next] = true;
+        self.next = (self.next + 1) % LAST_SEEN_COUNT;
+        self.offset += 1;
+    }
+
+    /// Acknowledges everything seen so far
+    fn update(&mut self) -> LastSeenMessagesUpdate {
+        let mut acknowledged = FixedBitSet::<LAST_SEEN_COUNT>::new();
+        for i in 0..LAST_SEEN_COUNT {
+            if self.seen[(self.next + i) % LAST_SEEN_COUNT] {
+                acknowledged.set(i);
+            }
+        }
+
+        LastSeenMessagesUpdate {
+            offset: std::mem::take(&mut self.offset),
+            acknowledged,
+        }
+    }
+
+    /// Takes note of what the controlling client acknowledged on its own
+    pub(super) fn acknowledged(&mut self, offset: u32) {
+        self.offset = self.offset.saturating_sub(offset);
+    }
+}
+
+impl SessionState {
+    /// Keeps the chat acknowledgements up to date with a packet from the
+    /// server
+    pub(super) fn track_chat(&mut self, packet: &ClientboundGamePacket) {
+        let ClientboundGamePacket::PlayerChat(packet) = packet else {
+            return;
+        };
+        if packet.signature.is_none() {
+            return;
+        }
+
+        self.acks.add();
+
+        // The controlling client acknowledges messages itself
+        if self.acks.offset > ACK_THRESHOLD && !self.is_controlled() {
+            let offset = std::mem::take(&mut self.acks.offset);
+            self.send_upstream(ServerboundChatAckPacket { offset }.get());
+        }
+    }
+
+    /// Notes the acknowledgements in a packet from the controlling client
+    pub(super) fn track_client_acks(&mut self, packet: &ServerboundGamePacket) {
+        let offset = match packet {
+            ServerboundGamePacket::Chat(packet) => packet.last_seen_messages.offset,
+            ServerboundGamePacket::ChatCommand(packet) => packet.last_seen_messages.offset,
+            ServerboundGamePacket::ChatAck(packet) => packet.offset,
+            _ => return,
+        };
+        self.acks.acknowledged(offset);
+    }
+
+    /// Acknowledges what the bot has seen for a message it sends itself
+    fn bot_chat_acks(&mut self) -> Result<LastSeenMessagesUpdate> {
+        if self.upstream.is_none() {
+            yeet!("The bot isn't on the server");
+        }
+
+        // The controlling client counts the messages it acknowledges, so the
+        // server would count ours twice and kick the bot
+        if self.is_controlled() {
+            yeet!("Someone is playing as the bot, so its chat has to come from them");
+        }
+
+        Ok(self.acks.update())
+    }
+}
+
+impl App {
+    /// Sends a chat message as the bot, or runs a command if it starts with
+    /// `/`. Neither are signed, and neither work while someone is playing as
+    /// the bot.
+    pub fn send_chat(&self, message: &str) -> Result<()> {
+        let mut state = self.bot.state.lock().unwrap();
+        let last_seen_messages = state.bot_chat_acks()?;
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let packet = match message.strip_prefix('/') {
+            Some(command) => ServerboundChatCommandPacket {
+                command: command.to_string(),
+                timestamp,
+                salt: azalea_crypto::make_salt(),
+                argument_signatures: vec![],
+                last_seen_messages,
+            }
+            .get(),
+            None => ServerboundChatPacket {
+                message: message.to_string(),
+                timestamp,
+                salt: azalea_crypto::make_salt(),
+                signature: None,
+                last_seen_messages,
+            }
+            .get(),
+        };
+        state.send_upstream(packet);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::app::bot::{clients::AttachedClient, Role};
+
+    #[test]
+    fn leaves_acks_to_the_controller() {
+        let mut state = SessionState::default();
+        let (upstream, _queue) = mpsc::unbounded_channel();
+        state.upstream = Some(upstream);
+        state.acks.add();
+        state.acks.add();
+
+        let (controller, _packets) = AttachedClient::new("Alice", Role::Controller);
+        state.clients.push(controller);
+        assert!(state.bot_chat_acks().is_err());
+        assert_eq!(state.acks.offset, 2);
+
+        // The controller acknowledges one of them
+        state.acks.acknowledged(1);
+        assert_eq!(state.acks.offset, 1);
+
+        state.clients.clear();
+        assert_eq!(state.bot_chat_acks().unwrap().offset, 1);
+        assert_eq!(state.acks.offset, 0);
+    }
+
+    #[test]
+    fn needs_the_bot_on_the_server() {
+        let mut state = SessionState::default();
+        assert!(state.bot_chat_acks().is_err());
+    }
+}
@@ -0,0 +1,504 @@
+use anyhow::{bail as yeet, Result};
+use azalea_chat::{text_component::TextComponent, FormattedText};
+use azalea_protocol::packets::game::{
+    clientbound_disconnect_packet::ClientboundDisconnectPacket,
+    serverbound_chat_ack_packet::ServerboundChatAckPacket, ClientboundGamePacket,
+    ServerboundGamePacket,
+};
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::{
+    sync::mpsc::{self, UnboundedReceiver, UnboundedSender},
+    task::JoinHandle,
+};
+use tracing::{debug, info};
+
+use crate::{
+    app::{
+        bot::{
+            cache::{dimension_swap, REPLAY_TELEPORT_ID},
+            write_queued, SessionState,
+        },
+        commands::system_message,
+        App,
+    },
+    conn::{ServerGameConn, WriteConnection},
+};
+
+static NEXT_CLIENT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// What a client attached to the bot is allowed to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    /// Plays as the bot
+    Controller,
+
+    /// Watches the bot without being able to do anything on the server
+    Spectator,
+}
+
+/// A connection to a client that is read in the background, so waiting for a
+/// packet can be given up on without losing half of one
+#[derive(Debug)]
+pub struct ClientConn {
+    /// Packets from the client. This closes once the client disconnects.
+    pub packets: UnboundedReceiver<ServerboundGamePacket>,
+    pub write: WriteConnection<ClientboundGamePacket>,
+    reader: JoinHandle<()>,
+}
+
+impl Drop for ClientConn {
+    fn drop(&mut self) {
+        self.reader.abort();
+    }
+}
+
+/// A player connected through the proxy
+#[derive(Debug)]
+pub(super) struct AttachedClient {
+    id: u64,
+    pub name: String,
+    pub role: Role,
+
+    /// Whether the client has caught up with where the bot is since it was
+    /// last put into the world. Until then, its movement is from before.
+    synced: bool,
+
+    /// Packets waiting to be sent to the client. Dropping this disconnects
+    /// the client once everything has been sent.
+    sender: UnboundedSender<ClientboundGamePacket>,
+}
+
+impl SessionState {
+    /// Sends a packet from the server to every client in the form they should
+    /// see it, forgetting about the ones that have disconnected
+    pub(super) fn broadcast(&mut self, packet: &ClientboundGamePacket) {
+        let bot_uuid = self.profile.as_ref().map(|profile| profile.uuid);
+        let cache = &self.cache;
+
+        self.clients.retain(|client| match (client.role, bot_uuid) {
+            (Role::Spectator, Some(bot_uuid)) => cache
+                .spectator_view(packet, bot_uuid)
+                .into_iter()
+                .all(|packet| client.sender.send(packet).is_ok()),
+            _ => client.sender.send(packet.clone()).is_ok(),
+        });
+    }
+
+    fn send_to(&self, id: u64, packet: ClientboundGamePacket) {
+        if let Some(client) = self.clients.iter().find(|client| client.id == id) {
+            let _ = client.sender.send(packet);
+        }
+    }
+
+    fn send_to_spectators(&self, packet: ClientboundGamePacket) {
+        for client in &self.clients {
+            if client.role == Role::Spectator {
+                let _ = client.sender.send(packet.clone());
+            }
+        }
+    }
+
+    fn role_of(&self, id: u64) -> Option<Role> {
+        self.clients
+            .iter()
+            .find(|client| client.id == id)
+            .map(|client| client.role)
+    }
+
+    /// The packets that put a client into the world in a role
+    ///
+    /// The client may still be in another world, like limbo, the last server
+    /// or the bot's world as it was before, so it's swapped out of that first.
+    fn replay_for(&self, role: Role) -> Vec<ClientboundGamePacket> {
+        let mut replay = match (role, &self.profile) {
+            (Role::Controller, _) => self.cache.replay(),
+            (Role::Spectator, Some(profile)) => self.cache.spectator_replay(profile.uuid),
+            (Role::Spectator, None) => vec![],
+        };
+        if let Some(ClientboundGamePacket::Login(login)) = replay.first() {
+            let swap = dimension_swap(login);
+            replay.splice(1..1, swap);
+        }
+        replay
+    }
+
+    /// Forgets the last server's world, returning the packets that clear what
+    /// clients still have on their screen from it
+    pub(super) fn leave_world(&mut self) -> Vec<ClientboundGamePacket> {
+        std::mem::take(&mut self.cache).leftovers()
+    }
+
+    /// Makes another client the controller, returning whether nobody had
+    /// control before
+    ///
+    /// Only the controller can hand off control. When nobody has it, only
+    /// `player` can take it.
+    fn hand_off(&mut self, from: u64, to: &str, player: &str) -> Result<bool> {
+        let controller = self.controller_index();
+        match controller {
+            Some(controller) if self.clients[controller].id != from => {
+                yeet!(
+                    "Only {} can hand off control",
+                    self.clients[controller].name
+                );
+            }
+            Some(_) => {}
+            None => {
+                let from = self.clients.iter().find(|client| client.id == from);
+                if from.map_or(true, |client| client.name != player) {
+                    yeet!("Only {player} can take control while nobody has it");
+                }
+            }
+        }
+        let Some(target) = self.clients.iter().position(|client| client.name == to) else {
+            yeet!("{to} isn't connected");
+        };
+        if Some(target) == controller {
+            yeet!("{to} is already in control");
+        }
+
+        if let Some(controller) = controller {
+            self.set_role(controller, Role::Spectator);
+        }
+        self.set_role(target, Role::Controller);
+
+        info!("{to} took control of the bot");
+        self.broadcast(&system_message(format!(
+            "{to} is now in control of the bot"
+        )));
+        Ok(controller.is_none())
+    }
+
+    /// Moves every client from the last server's world into the new one,
+    /// clearing away what's left from the last server first
+    pub(super) fn resync_clients(&mut self, leftovers: Vec<ClientboundGamePacket>) {
+        for index in 0..self.clients.len() {
+            let role = self.clients[index].role;
+            let replay = self.replay_for(role);
+            let packets = leftovers.iter().cloned().chain(replay).collect();
+            self.put_in_world(index, role, packets);
+        }
+    }
+
+    /// Changes what a client is allowed to do and puts it back into the world
+    /// accordingly
+    fn set_role(&mut self, index: usize, role: Role) {
+        let replay = self.replay_for(role);
+        self.put_in_world(index, role, replay);
+    }
+
+    fn put_in_world(&mut self, index: usize, role: Role, packets: Vec<ClientboundGamePacket>) {
+        let client = &mut self.clients[index];
+        client.role = role;
+        client.synced = false;
+        for packet in packets {
+            let _ = client.sender.send(packet);
+        }
+    }
+}
+
+impl AttachedClient {
+    /// Creates a client along with where its packets come out
+    pub(super) fn new(name: &str, role: Role) -> (Self, UnboundedReceiver<ClientboundGamePacket>) {
+        let (sender, queue) = mpsc::unbounded_channel();
+        let client = Self {
+            id: NEXT_CLIENT_ID.fetch_add(1, Ordering::Relaxed),
+            name: name.to_string(),
+            role,
+            synced: false,
+            sender,
+        };
+        (client, queue)
+    }
+
+    /// Kicks the client once everything before this has been sent
+    fn disconnect(self, reason: &str) {
+        let packet = ClientboundDisconnectPacket {
+            reason: FormattedText::Text(TextComponent::new(reason.to_string())),
+        };
+        let _ = self.sender.send(packet.get());
+    }
+}
+
+impl App {
+    /// Starts reading packets from a client in the background
+    pub fn client_conn(&self, conn: ServerGameConn) -> ClientConn {
+        let (mut read, write) = conn.into_split();
+        let (sender, packets) = mpsc::unbounded_channel();
+
+        let app = self.clone();
+        let reader = tokio::spawn(async move {
+            loop {
+                let packet = match read.read().await {
+                    Ok(packet) => packet,
+                    Err(err) => {
+                        debug!("Stopped reading from the client: {err}");
+                        return;
+                    }
+                };
+                if sender.send(packet).is_err() {
+                    return;
+                }
+            }
+        });
+
+        ClientConn {
+            packets,
+            write,
+            reader,
+        }
+    }
+
+    /// Puts a client into the bot's world and relays packets for them until
+    /// they disconnect
+    ///
+    /// The packets in `backlog` are sent to the client right after the world.
+    pub async fn attach(
+        &self,
+        name: &str,
+        role: Role,
+        mut conn: ClientConn,
+        backlog: Vec<ClientboundGamePacket>,
+    ) -> Result<()> {
+        let (client, mut queue) = AttachedClient::new(name, role);
+        let id = client.id;
+
+        {
+            let mut state = self.bot.state.lock().unwrap();
+            if state.profile.is_none() || !state.cache.is_ready() {
+                yeet!("The bot isn't in the world yet");
+            }
+
+            for packet in state.replay_for(role).into_iter().chain(backlog) {
+                let _ = client.sender.send(packet);
+            }
+
+            // Only one player can be in control at a time
+            if role == Role::Controller {
+                if let Some(index) = state.controller_index() {
+                    state.set_role(index, Role::Spectator);
+                    let _ = state.clients[index]
+                        .sender
+                        .send(system_message(format!("{name} took control of the bot")));
+                }
+            }
+
+            state.clients.push(client);
+        }
+
+        info!("{name} attached as {role:?}");
+
+        let result = tokio::select! {
+            result = self.read_client(id, &mut conn.packets) => result,
+            result = write_queued(&mut conn.write, &mut queue) => result,
+        };
+
+        self.detach(id);
+        info!("{name} detached");
+
+        result
+    }
+
+    /// Disconnects every client, for example because the bot lost its
+    /// connection
+    pub(super) fn detach_all(&self, reason: &str) {
+        let clients = std::mem::take(&mut self.bot.state.lock().unwrap().clients);
+        for client in clients {
+            client.disconnect(reason);
+        }
+        self.bot.go_away();
+    }
+
+    /// Whether a client can run commands that change something, which only
+    /// the controller and `player` from the config can
+    pub fn is_in_charge(&self, client: u64) -> bool {
+        let player = self.config.get().player.clone();
+        let state = self.bot.state.lock().unwrap();
+        state.clients.iter().any(|attached| {
+            attached.id == client && (attached.role == Role::Controller || attached.name == player)
+        })
+    }
+
+    /// Makes another attached client the controller, demoting the current one
+    /// to spectator
+    pub fn hand_off(&self, from: u64, to: &str) -> Result<()> {
+        let player = self.config.get().player.clone();
+        let claimed = self.bot.state.lock().unwrap().hand_off(from, to, &player)?;
+        if claimed {
+            self.bot.come_back();
+        }
+        Ok(())
+    }
+
+    fn detach(&self, id: u64) {
+        let mut state = self.bot.state.lock().unwrap();
+        state.clients.retain(|client| client.id != id);
+        if !state.is_controlled() {
+            self.bot.go_away();
+        }
+    }
+
+    /// Handles packets from a client until it disconnects
+    async fn read_client(
+        &self,
+        id: u64,
+        packets: &mut UnboundedReceiver<ServerboundGamePacket>,
+    ) -> Result<()> {
+        loop {
+            let Some(packet) = packets.recv().await else {
+                yeet!("Lost connection to the client");
+            };
+
+            if let ServerboundGamePacket::ChatCommand(command) = &packet {
+                if let Some(reply) = self.run_command(id, &command.command).await {
+                    let state = self.bot.state.lock().unwrap();
+                    for line in reply {
+                        state.send_to(id, system_message(line));
+                    }
+
+                    // The server keeps count of the chat messages the client
+                    // has seen, so it still needs to hear about the ones that
+                    // were acknowledged along with the command
+                    let offset = command.last_seen_messages.offset;
+                    if offset > 0 && state.role_of(id) == Some(Role::Controller) {
+                        state.send_upstream(ServerboundChatAckPacket { offset }.get());
+                    }
+                    continue;
+                }
+            }
+
+            self.forward_upstream(id, packet);
+        }
+    }
+
+    /// Sends a packet from a client to the server, if the client is allowed to
+    fn forward_upstream(&self, id: u64, packet: ServerboundGamePacket) {
+        let mut state = self.bot.state.lock().unwrap();
+        let Some(client) = state.clients.iter_mut().find(|client| client.id == id) else {
+            return;
+        };
+
+        match &packet {
+            // We answer the server's keep-alives ourselves
+            ServerboundGamePacket::KeepAlive(_) => return,
+            // The server doesn't know about the teleport we made up, but it
+            // means the client is where the bot is now
+            ServerboundGamePacket::AcceptTeleportation(packet)
+                if packet.id == REPLAY_TELEPORT_ID =>
+            {
+                client.synced = true;
+                return;
+            }
+            _ => {}
+        }
+
+        if client.role != Role::Controller || !client.synced {
+            return;
+        }
+        state.track_client_acks(&packet);
+
+        // Spectators can't see the bot move unless we tell them
+        if state.cache.apply_movement(&packet) {
+            if let Some(teleport) = state.cache.bot_teleport_packet() {
+                if let Some(recorder) = &mut state.recorder {
+                    recorder.record(&teleport);
+                }
+                state.send_to_spectators(teleport);
+            }
+        }
+
+        state.send_upstream(packet);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use azalea_auth::game_profile::GameProfile;
+    use azalea_core::GameType;
+    use azalea_protocol::packets::game::{
+        clientbound_boss_event_packet::{ClientboundBossEventPacket, Operation},
+        clientbound_player_info_update_packet::{
+            ActionEnumSet, ClientboundPlayerInfoUpdatePacket, PlayerInfoEntry,
+        },
+    };
+    use uuid::Uuid;
+
+    use super::*;
+
+    #[test]
+    fn clears_the_last_server_away() {
+        let mut state = SessionState::default();
+        let player = Uuid::from_u128(1);
+        let boss_bar = Uuid::from_u128(2);
+        let tab_entry = ClientboundPlayerInfoUpdatePacket {
+            actions: ActionEnumSet {
+                add_player: true,
+                initialize_chat: false,
+                update_game_mode: false,
+                update_listed: false,
+                update_latency: false,
+                update_display_name: false,
+            },
+            entries: vec![PlayerInfoEntry {
+                profile: GameProfile::new(player, "Alice".to_string()),
+                listed: true,
+                latency: 0,
+                game_mode: GameType::Survival,
+                display_name: None,
+                chat_session: None,
+            }],
+        };
+        state.cache.update(&tab_entry.get());
+        let boss_event = ClientboundBossEventPacket {
+            id: boss_bar,
+            operation: Operation::UpdateProgress(0.5),
+        };
+        state.cache.update(&boss_event.get());
+
+        let (client, mut packets) = AttachedClient::new("Bob", Role::Controller);
+        state.clients.push(client);
+
+        // What happens between switching servers and the new server's login
+        let leftovers = state.leave_world();
+        state.resync_clients(leftovers);
+
+        let mut removed_players = vec![];
+        let mut removed_boss_bars = vec![];
+        while let Ok(packet) = packets.try_recv() {
+            match packet {
+                ClientboundGamePacket::PlayerInfoRemove(packet) => {
+                    removed_players.extend(packet.profile_ids);
+                }
+                ClientboundGamePacket::BossEvent(ClientboundBossEventPacket {
+                    id,
+                    operation: Operation::Remove,
+                }) => removed_boss_bars.push(id),
+                _ => {}
+            }
+        }
+        assert_eq!(removed_players, [player]);
+        assert_eq!(removed_boss_bars, [boss_bar]);
+    }
+
+    #[test]
+    fn only_the_player_can_claim_control() {
+        let mut state = SessionState::default();
+        let (eve, _eve_packets) = AttachedClient::new("Eve", Role::Spectator);
+        let (alice, _alice_packets) = AttachedClient::new("Alice", Role::Spectator);
+        let (eve_id, alice_id) = (eve.id, alice.id);
+        state.clients.push(eve);
+        state.clients.push(alice);
+
+        assert!(state.hand_off(eve_id, "Eve", "Alice").is_err());
+        assert!(state.hand_off(eve_id, "Alice", "Alice").is_err());
+        assert!(!state.is_controlled());
+
+        assert!(state.hand_off(alice_id, "Alice", "Alice").unwrap());
+        assert_eq!(state.clients[1].role, Role::Controller);
+
+        // Once in control, Alice can pass it on
+        assert!(!state.hand_off(alice_id, "Eve", "Alice").unwrap());
+        assert_eq!(state.clients[0].role, Role::Controller);
+        assert_eq!(state.clients[1].role, Role::Spectator);
+    }
+}
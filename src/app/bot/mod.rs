@@ -0,0 +1,461 @@
+use anyhow::{bail as yeet, Result};
+use azalea_auth::game_profile::GameProfile;
+use azalea_protocol::packets::game::{
+    serverbound_accept_teleportation_packet::ServerboundAcceptTeleportationPacket,
+    serverbound_keep_alive_packet::ServerboundKeepAlivePacket, ClientboundGamePacket,
+    ServerboundGamePacket,
+};
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tokio::{
+    sync::{
+        mpsc::{self, UnboundedReceiver, UnboundedSender},
+        watch,
+    },
+    task::JoinHandle,
+};
+use tracing::{info, warn};
+
+use crate::{
+    app::App,
+    chat_log::ChatMessage,
+    config::ForwardingMode,
+    conn::{Packets, ReadConnection, WriteConnection},
+    events::EventKind,
+    forwarding::VelocityForwarding,
+    join::join_server,
+    login_queries::{CustomQueryHandler, QueryResponders},
+    replay::Recorder,
+    store::LastPosition,
+};
+
+use self::{
+    auth::LoggedIn, cache::WorldCache, chat::ChatAcks, clients::AttachedClient,
+    recording::save_recording,
+};
+
+pub use self::{
+    clients::{ClientConn, Role},
+    safety::SafetyTrigger,
+};
+
+mod auth;
+mod auto_reply;
+mod cache;
+mod chat;
+mod clients;
+mod persistence;
+mod queue;
+mod recording;
+mod safety;
+mod servers;
+mod visual_range;
+
+/// Keeps track of the bot that holds the spot on the server. Players attach to
+/// it to play, and it keeps going on its own while nobody is controlling it.
+#[derive(Debug)]
+pub struct BotControl {
+    task: Mutex<Option<JoinHandle<()>>>,
+
+    /// The server the bot was moved to, if it isn't on the default one
+    server: Mutex<Option<String>>,
+
+    /// Whether the bot is on the server right now
+    status: watch::Sender<BotStatus>,
+
+    /// Everything about the current session that has to change together
+    state: Mutex<SessionState>,
+
+    /// The safety trigger that logged the bot out, if any. While this is set,
+    /// the bot won't reconnect.
+    tripped: Mutex<Option<SafetyTrigger>>,
+
+    /// When the player left the bot in charge
+    away_since: Mutex<Option<DateTime<Utc>>>,
+
+    /// Whispers that came in while the player was away, oldest first
+    whispers: Mutex<VecDeque<ChatMessage>>,
+
+    /// The last queue position the bot was told about
+    queue_position: Mutex<Option<u32>>,
+
+    /// When we last auto-replied to each player
+    replied: Mutex<HashMap<String, Instant>>,
+
+    /// The account the bot last logged in as. Held while logging in so
+    /// nobody logs in twice at once.
+    logged_in: tokio::sync::Mutex<Option<LoggedIn>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BotStatus {
+    Offline,
+    Connecting,
+    Online,
+
+    /// The bot lost its connection and will try again soon
+    Disconnected(String),
+}
+
+#[derive(Debug, Default)]
+struct SessionState {
+    /// Who the bot is logged in as
+    profile: Option<GameProfile>,
+
+    cache: WorldCache,
+    clients: Vec<AttachedClient>,
+
+    /// Packets waiting to be sent to the server
+    upstream: Option<UnboundedSender<ServerboundGamePacket>>,
+
+    recorder: Option<Recorder>,
+
+    /// The signed messages the bot has to acknowledge
+    acks: ChatAcks,
+}
+
+impl Default for BotControl {
+    fn default() -> Self {
+        Self {
+            task: Mutex::default(),
+            server: Mutex::default(),
+            status: watch::channel(BotStatus::Offline).0,
+            state: Mutex::default(),
+            tripped: Mutex::default(),
+            away_since: Mutex::default(),
+            whispers: Mutex::default(),
+            queue_position: Mutex::default(),
+            replied: Mutex::default(),
+            logged_in: tokio::sync::Mutex::default(),
+        }
+    }
+}
+
+impl BotControl {
+    /// Takes the safety trigger that fired since the player was last here,
+    /// which counts as the player acknowledging it
+    pub fn acknowledge(&self) -> Option<SafetyTrigger> {
+        self.tripped.lock().unwrap().take()
+    }
+
+    /// Takes the whispers that came in while the player was away
+    pub fn take_whispers(&self) -> Vec<ChatMessage> {
+        self.whispers.lock().unwrap().drain(..).collect()
+    }
+
+    /// How long the player has been away, if they are
+    pub fn away_for(&self) -> Option<Duration> {
+        let since = (*self.away_since.lock().unwrap())?;
+        (Utc::now() - since).to_std().ok()
+    }
+
+    /// Marks the player as back and returns since when they were away
+    pub fn come_back(&self) -> Option<DateTime<Utc>> {
+        self.away_since.lock().unwrap().take()
+    }
+
+    /// Marks the player as away, unless they already are
+    fn go_away(&self) {
+        self.away_since.lock().unwrap().get_or_insert_with(Utc::now);
+    }
+
+    /// Who the bot is logged in as
+    pub fn profile(&self) -> Option<GameProfile> {
+        self.state.lock().unwrap().profile.clone()
+    }
+
+    pub fn status(&self) -> BotStatus {
+        self.status.borrow().clone()
+    }
+
+    /// Watches the bot's status as it changes
+    pub fn subscribe_status(&self) -> watch::Receiver<BotStatus> {
+        self.status.subscribe()
+    }
+
+    /// Whether the bot is running, even if it's between connections
+    pub fn is_running(&self) -> bool {
+        self.task
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map_or(false, |task| !task.is_finished())
+    }
+
+    fn tripped(&self) -> Option<SafetyTrigger> {
+        self.tripped.lock().unwrap().clone()
+    }
+
+    fn trip(&self, trigger: SafetyTrigger) {
+        *self.tripped.lock().unwrap() = Some(trigger);
+    }
+
+    fn set_status(&self, status: BotStatus) {
+        self.status.send_replace(status);
+    }
+}
+
+impl App {
+    /// Starts the bot in the background, stopping the previous one if needed
+    pub fn start_bot(&self) {
+        self.bot.set_status(BotStatus::Connecting);
+        self.store.update(|state| state.bot_running = true);
+
+        let app_clone = self.clone();
+        let task = tokio::spawn(async move { app_clone.run_bot().await });
+        if let Some(old) = self.bot.task.lock().unwrap().replace(task) {
+            old.abort();
+        }
+    }
+
+    /// Takes the bot off the server for good, kicking everyone attached to it
+    pub async fn stop_bot(&self, reason: &str) {
+        if let Some(task) = self.bot.task.lock().unwrap().take() {
+            task.abort();
+        }
+        self.detach_all(reason);
+
+        let recorder = {
+            let mut state = self.bot.state.lock().unwrap();
+            state.upstream = None;
+            state.recorder.take()
+        };
+        if let Some(recorder) = recorder {
+            save_recording(recorder).await;
+        }
+
+        self.bot.set_status(BotStatus::Offline);
+    }
+
+    /// Keeps the bot on the server, reconnecting whenever it gets kicked until
+    /// a safety trigger fires
+    async fn run_bot(&self) {
+        loop {
+            if let Some(trigger) = self.bot.tripped() {
+                warn!("Not reconnecting the bot since it was logged out because it {trigger}");
+                self.bot.set_status(BotStatus::Offline);
+                return;
+            }
+
+            self.bot.set_status(BotStatus::Connecting);
+            let reason = match self.run_bot_session().await {
+                Ok(trigger) => {
+                    warn!("Logging the bot out because it {trigger}");
+                    self.events.emit(EventKind::BotDisconnected {
+                        reason: format!("logged out because it {trigger}"),
+                    });
+                    self.detach_all(&format!("The bot was logged out because it {trigger}"));
+                    self.store.update(|state| {
+                        state.bot_running = false;
+                        state.tripped = Some(trigger.clone());
+                    });
+                    self.bot.trip(trigger);
+                    continue;
+                }
+                Err(err) => {
+                    warn!("Bot disconnected: {err}");
+                    err.to_string()
+                }
+            };
+
+            self.events.emit(EventKind::BotDisconnected {
+                reason: reason.clone(),
+            });
+            self.detach_all(&format!("Lost connection to the server: {reason}"));
+            self.bot.set_status(BotStatus::Disconnected(reason));
+
+            let reconnect_delay = Duration::from_secs(self.config.get().bot.reconnect_delay);
+            info!("Reconnecting in {} seconds", reconnect_delay.as_secs());
+            tokio::time::sleep(reconnect_delay).await;
+        }
+    }
+
+    /// Joins the server and keeps the session going until either the
+    /// connection dies or a safety trigger fires
+    async fn run_bot_session(&self) -> Result<SafetyTrigger> {
+        let config = self.config.get();
+        let account = self.authenticate().await?;
+
+        // Servers behind BungeeCord or Velocity expect to be told who's joining
+        let player = self.forwarded_player(&account).await;
+        let responders = QueryResponders::from_config(&config.login_queries)?;
+        let velocity;
+        let (queries, bungeecord): (&dyn CustomQueryHandler, _) =
+            match (config.forwarding.mode, &player) {
+                (ForwardingMode::Bungeecord, Some(player)) => (&responders, Some(player)),
+                (ForwardingMode::Velocity, Some(player)) => {
+                    velocity = VelocityForwarding {
+                        secret: &config.forwarding.secret,
+                        player,
+                        fallback: &responders,
+                    };
+                    (&velocity, None)
+                }
+                _ => (&responders, None),
+            };
+
+        let (conn, profile) = join_server(
+            &self.server_addr(),
+            &account,
+            config.outbound_proxy.as_ref(),
+            self.capture.as_ref(),
+            queries,
+            bungeecord,
+        )
+        .await?;
+
+        info!("Successfully connected as {}", profile.name);
+
+        let (mut read, mut write) = conn.into_split();
+        let (upstream, mut queue) = mpsc::unbounded_channel();
+        // Clients still attached from before the bot switched servers have to
+        // be cleared of the last one once the new one's login comes in
+        let leftovers = {
+            let mut state = self.bot.state.lock().unwrap();
+            state.profile = Some(profile);
+            state.acks = ChatAcks::default();
+            state.upstream = Some(upstream);
+            state.leave_world()
+        };
+
+        let result = tokio::select! {
+            result = self.read_upstream(&mut read, leftovers) => result,
+            result = write_queued(&mut write, &mut queue) => {
+                result.and_then(|_| Err(anyhow::anyhow!("Stopped writing to the server")))
+            }
+        };
+
+        let recorder = {
+            let mut state = self.bot.state.lock().unwrap();
+            state.upstream = None;
+            state.recorder.take()
+        };
+        if let Some(recorder) = recorder {
+            save_recording(recorder).await;
+        }
+
+        result
+    }
+
+    /// Handles everything the server sends until a safety trigger fires
+    async fn read_upstream(
+        &self,
+        read: &mut ReadConnection<ClientboundGamePacket>,
+        mut leftovers: Vec<ClientboundGamePacket>,
+    ) -> Result<SafetyTrigger> {
+        let whisper_pattern = self.whisper_pattern()?;
+        let queue_pattern = Regex::new(&self.config.get().notifications.queue_pattern)?;
+        *self.bot.queue_position.lock().unwrap() = None;
+
+        loop {
+            let packet = read.read().await?;
+
+            let controlled = {
+                let mut state = self.bot.state.lock().unwrap();
+                self.check_visual_range(&state.cache, &packet);
+                state.cache.update(&packet);
+                state.record(&packet);
+                state.track_chat(&packet);
+
+                // Clients still attached from before the bot switched servers
+                // need to be put into the new world from scratch
+                if let ClientboundGamePacket::Login(_) = &packet {
+                    state.resync_clients(std::mem::take(&mut leftovers));
+                } else {
+                    state.broadcast(&packet);
+                }
+
+                match &packet {
+                    ClientboundGamePacket::Login(_) => {
+                        self.bot.set_status(BotStatus::Online);
+                        self.events.emit(EventKind::BotConnected {
+                            server: self.server_name(),
+                        });
+                        if state.recorder.is_none() {
+                            self.spawn_auto_recording();
+                        }
+                    }
+                    // The server only needs to hear back from one client, so
+                    // we answer for all of them
+                    ClientboundGamePacket::KeepAlive(packet) => {
+                        state.send_upstream(ServerboundKeepAlivePacket { id: packet.id }.get());
+                    }
+                    ClientboundGamePacket::PlayerPosition(packet) if !state.is_controlled() => {
+                        let packet = ServerboundAcceptTeleportationPacket { id: packet.id };
+                        state.send_upstream(packet.get());
+                    }
+                    ClientboundGamePacket::PlayerCombatKill(packet)
+                        if Some(packet.player_id) == state.cache.entity_id() =>
+                    {
+                        let message = packet.message.to_string();
+                        let position = state.cache.known_position().map(|position| LastPosition {
+                            server: self.server_name(),
+                            x: position.x,
+                            y: position.y,
+                            z: position.z,
+                        });
+                        self.store
+                            .update(|state| state.push_death(message.clone(), position));
+                        self.events.emit(EventKind::Died { message });
+                    }
+                    ClientboundGamePacket::Disconnect(packet) => {
+                        yeet!("Kicked: {}", packet.reason);
+                    }
+                    _ => {}
+                }
+
+                // Whoever is in control can look after themselves
+                let controlled = state.is_controlled();
+                if !controlled {
+                    if let Some(trigger) = safety::check(&self.config.get(), &state.cache, &packet)
+                    {
+                        return Ok(trigger);
+                    }
+                }
+                controlled
+            };
+
+            if let Some(message) = self.record_chat(&packet).await {
+                self.check_queue_position(&queue_pattern, &message);
+                if !controlled {
+                    self.handle_whisper(&whisper_pattern, &message);
+                }
+            }
+        }
+    }
+}
+
+impl SessionState {
+    /// Whether a player is controlling the bot
+    fn is_controlled(&self) -> bool {
+        self.controller_index().is_some()
+    }
+
+    fn controller_index(&self) -> Option<usize> {
+        self.clients
+            .iter()
+            .position(|client| client.role == Role::Controller)
+    }
+
+    fn send_upstream(&self, packet: ServerboundGamePacket) {
+        if let Some(upstream) = &self.upstream {
+            let _ = upstream.send(packet);
+        }
+    }
+}
+
+/// Writes everything from the queue to a connection
+async fn write_queued<W: Packets>(
+    conn: &mut WriteConnection<W>,
+    queue: &mut UnboundedReceiver<W>,
+) -> Result<()> {
+    while let Some(packet) = queue.recv().await {
+        conn.write(packet).await?;
+    }
+    Ok(())
+}
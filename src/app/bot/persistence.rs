@@ -0,0 +1,66 @@
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::{app::App, store::LastPosition};
+
+/// How often the state is written to disk if it changed
+const SAVE_INTERVAL: Duration = Duration::from_secs(10);
+
+impl App {
+    /// Picks up where the proxy left off before it was restarted
+    pub fn restore_session(&self) {
+        let state = self.store.get();
+        *self.bot.server.lock().unwrap() = state.server;
+        *self.bot.tripped.lock().unwrap() = state.tripped.clone();
+
+        if let Some(trigger) = state.tripped {
+            info!("The bot was logged out before the restart because it {trigger}");
+        } else if state.bot_running {
+            info!("The bot was on the server before the restart, reconnecting");
+            self.start_bot();
+        }
+    }
+
+    /// Writes the state to disk every now and then, along with where the bot
+    /// is
+    pub fn spawn_state_saver(&self) {
+        let app = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(SAVE_INTERVAL);
+            loop {
+                interval.tick().await;
+                app.save_state().await;
+            }
+        });
+    }
+
+    pub async fn save_state(&self) {
+        self.remember_position();
+        if let Err(err) = self.store.save().await {
+            warn!("Failed to save the state: {err}");
+        }
+    }
+
+    fn remember_position(&self) {
+        let Some(position) = self.bot.state.lock().unwrap().cache.known_position() else {
+            return;
+        };
+        let position = LastPosition {
+            server: self.server_name(),
+            x: position.x,
+            y: position.y,
+            z: position.z,
+        };
+
+        let unchanged = self.store.get().last_position.map_or(false, |last| {
+            last.server == position.server
+                && last.x == position.x
+                && last.y == position.y
+                && last.z == position.z
+        });
+        if !unchanged {
+            self.store
+                .update(|state| state.last_position = Some(position));
+        }
+    }
+}
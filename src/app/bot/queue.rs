@@ -0,0 +1,37 @@
+use regex::Regex;
+use tracing::info;
+
+use crate::{app::App, chat_log::ChatMessage, events::EventKind};
+
+impl App {
+    /// Announces the bot's queue position if the message is about it and the
+    /// bot has just reached one of the milestones
+    pub(super) fn check_queue_position(&self, pattern: &Regex, message: &ChatMessage) {
+        let Some(position) = pattern
+            .captures(&message.message)
+            .and_then(|captures| captures.name("position"))
+            .and_then(|position| position.as_str().parse::<u32>().ok())
+        else {
+            return;
+        };
+
+        let previous = self.bot.queue_position.lock().unwrap().replace(position);
+        if previous != Some(position) {
+            self.store.update(|state| state.push_queue(position));
+        }
+        let reached = self
+            .config
+            .get()
+            .notifications
+            .queue_milestones
+            .iter()
+            .any(|&milestone| {
+                position <= milestone && previous.map_or(true, |previous| previous > milestone)
+            });
+
+        if reached {
+            info!("The bot is at {position} in the queue");
+            self.events.emit(EventKind::QueuePosition { position });
+        }
+    }
+}
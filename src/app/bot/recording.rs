@@ -0,0 +1,106 @@
+use anyhow::{bail as yeet, Result};
+use azalea_protocol::packets::game::ClientboundGamePacket;
+use std::path::PathBuf;
+use tracing::{error, info, warn};
+
+use crate::{
+    app::{bot::SessionState, App},
+    replay::Recorder,
+};
+
+impl App {
+    /// Starts recording the bot's session, which keeps going until it's
+    /// stopped or the bot disconnects
+    pub async fn start_recording(&self) -> Result<()> {
+        let Some(profile) = self.bot.profile() else {
+            yeet!("The bot isn't on the server");
+        };
+        if self.bot.state.lock().unwrap().recorder.is_some() {
+            yeet!("Already recording");
+        }
+
+        let mut recorder = Recorder::start(
+            &self.config.get().replay.directory,
+            self.server_name(),
+            &profile,
+        )
+        .await?;
+
+        let mut state = self.bot.state.lock().unwrap();
+        if state.recorder.is_some() {
+            yeet!("Already recording");
+        }
+        if !state.cache.is_ready() {
+            yeet!("The bot isn't in the world yet");
+        }
+
+        // Start with everything the bot can see right now
+        for packet in state.cache.replay() {
+            recorder.record(&packet);
+        }
+        if let Some(spawn) = state.cache.bot_spawn_packet(profile.uuid) {
+            recorder.record(&spawn);
+        }
+        state.recorder = Some(recorder);
+
+        info!("Started recording");
+        Ok(())
+    }
+
+    /// Stops recording, returning where the recording was saved
+    pub async fn stop_recording(&self) -> Result<PathBuf> {
+        let Some(recorder) = self.bot.state.lock().unwrap().recorder.take() else {
+            yeet!("Not recording");
+        };
+
+        let path = recorder.finish().await?;
+        info!("Saved recording to {}", path.display());
+        Ok(path)
+    }
+
+    /// Starts recording in the background if recordings are enabled
+    pub(super) fn spawn_auto_recording(&self) {
+        if !self.config.get().replay.enabled && !self.store.get().recording {
+            return;
+        }
+
+        let app = self.clone();
+        tokio::spawn(async move {
+            if let Err(err) = app.start_recording().await {
+                error!("Failed to start recording: {err}");
+            }
+        });
+    }
+}
+
+impl SessionState {
+    /// Adds a packet from the server to the recording, if there is one
+    pub(super) fn record(&mut self, packet: &ClientboundGamePacket) {
+        let Some(recorder) = &mut self.recorder else {
+            return;
+        };
+        recorder.record(packet);
+
+        // The server never tells the bot about its own player entity, but the
+        // recording should show it
+        let bot = match packet {
+            ClientboundGamePacket::Login(_) | ClientboundGamePacket::Respawn(_) => self
+                .profile
+                .as_ref()
+                .and_then(|profile| self.cache.bot_spawn_packet(profile.uuid)),
+            ClientboundGamePacket::PlayerPosition(_) => self.cache.bot_teleport_packet(),
+            _ => None,
+        };
+        if let Some(bot) = bot {
+            recorder.record(&bot);
+        }
+    }
+}
+
+/// Finishes a recording that nobody is waiting for, logging how it went
+pub(super) async fn save_recording(recorder: Recorder) {
+    match recorder.finish().await {
+        Ok(path) => info!("Saved recording to {}", path.display()),
+        Err(err) => warn!("Failed to save the recording: {err}"),
+    }
+}
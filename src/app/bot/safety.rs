@@ -0,0 +1,73 @@
+use azalea_protocol::packets::game::ClientboundGamePacket;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::{app::bot::cache::WorldCache, config::Config};
+
+/// How close another player has to be for damage to be blamed on them
+const PLAYER_DAMAGE_RADIUS: f64 = 8.0;
+
+/// A reason for the bot to log out and stay logged out
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SafetyTrigger {
+    LowHealth(f32),
+    UnknownPlayer(String),
+    NoTotems,
+    PlayerDamage(String),
+}
+
+impl fmt::Display for SafetyTrigger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::LowHealth(health) => write!(f, "health dropped to {health}"),
+            Self::UnknownPlayer(name) => write!(f, "{name} entered render distance"),
+            Self::NoTotems => write!(f, "ran out of totems"),
+            Self::PlayerDamage(name) => write!(f, "took damage with {name} nearby"),
+        }
+    }
+}
+
+/// Checks the world against every enabled safety trigger after it has been
+/// updated with `packet` and returns the first one that fired
+pub fn check(
+    config: &Config,
+    world: &WorldCache,
+    packet: &ClientboundGamePacket,
+) -> Option<SafetyTrigger> {
+    let safety = &config.safety;
+
+    if let (Some(min_health), Some(health)) = (safety.min_health, world.health) {
+        if health < min_health {
+            return Some(SafetyTrigger::LowHealth(health));
+        }
+    }
+
+    if safety.unknown_player {
+        // Players without a name yet are checked once their tab list entry
+        // comes in
+        let stranger = world
+            .players()
+            .filter_map(|player| world.player_name(&player.uuid))
+            .find(|name| !config.is_friend(name));
+        if let Some(name) = stranger {
+            return Some(SafetyTrigger::UnknownPlayer(name));
+        }
+    }
+
+    if safety.no_totems && world.totem_count() == Some(0) {
+        return Some(SafetyTrigger::NoTotems);
+    }
+
+    // The protocol doesn't tell us who hit us, so blame anyone close by
+    let health_changed = matches!(packet, ClientboundGamePacket::SetHealth(_));
+    if safety.player_damage && health_changed && world.took_damage() {
+        if let Some(player) = world.players_within(PLAYER_DAMAGE_RADIUS).next() {
+            let name = world
+                .player_name(&player.uuid)
+                .unwrap_or_else(|| player.uuid.to_string());
+            return Some(SafetyTrigger::PlayerDamage(name));
+        }
+    }
+
+    None
+}
@@ -0,0 +1,78 @@
+use anyhow::{bail as yeet, Result};
+use std::net::SocketAddr;
+use tracing::info;
+
+use crate::app::{bot::recording::save_recording, commands::system_message, App};
+
+/// The name of the server from `server_addr` in the config
+const DEFAULT_SERVER: &str = "default";
+
+impl App {
+    /// The name of the server the bot is on, or will join next
+    pub fn server_name(&self) -> String {
+        self.bot
+            .server
+            .lock()
+            .unwrap()
+            .clone()
+            .unwrap_or_else(|| DEFAULT_SERVER.to_string())
+    }
+
+    /// The address of the server the bot is on, or will join next
+    pub fn server_addr(&self) -> SocketAddr {
+        self.server_addr_of(&self.server_name())
+            .unwrap_or(self.config.get().server_addr)
+    }
+
+    /// Looks up a server from the config by name
+    fn server_addr_of(&self, name: &str) -> Option<SocketAddr> {
+        match name {
+            DEFAULT_SERVER => Some(self.config.get().server_addr),
+            name => self.config.get().servers.get(name).copied(),
+        }
+    }
+
+    /// Moves the bot to another server, taking everyone attached to it along
+    ///
+    /// Clients stay connected and are put into the new world as soon as the
+    /// bot has joined it.
+    pub async fn switch_server(&self, name: &str) -> Result<()> {
+        let Some(addr) = self.server_addr_of(name) else {
+            yeet!("There's no server called {name}");
+        };
+        if name == self.server_name() && self.bot.is_running() {
+            yeet!("The bot is already on {name}");
+        }
+
+        info!("Switching to {name} ({addr})");
+        *self.bot.server.lock().unwrap() = Some(name.to_string());
+        self.store
+            .update(|state| state.server = Some(name.to_string()));
+
+        // This drops the connection to the old server without kicking anyone.
+        // Waiting for it to stop makes sure it's gone for good before we
+        // forget about it.
+        let old = self.bot.task.lock().unwrap().take();
+        if let Some(old) = old {
+            old.abort();
+            let _ = old.await;
+        }
+
+        // Nobody is on the other end of the old connection anymore, so nothing
+        // can go upstream until the bot is on the new server
+        let recorder = {
+            let mut state = self.bot.state.lock().unwrap();
+            state.upstream = None;
+            state.profile = None;
+            state.broadcast(&system_message(format!("Switching to {name}...")));
+            state.recorder.take()
+        };
+        self.start_bot();
+
+        if let Some(recorder) = recorder {
+            save_recording(recorder).await;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,126 @@
+use anyhow::Result;
+use azalea_protocol::packets::game::ClientboundGamePacket;
+use std::path::Path;
+use tokio::{fs::OpenOptions, io::AsyncWriteExt, sync::broadcast::error::RecvError};
+use tracing::{error, info, warn};
+
+use crate::{
+    app::{
+        bot::cache::{TrackedPlayer, WorldCache},
+        App,
+    },
+    events::{EventBus, EventKind, PlayerSighting},
+};
+
+impl App {
+    /// Emits an event for every player that is about to enter or leave render
+    /// distance because of `packet`
+    ///
+    /// This has to be called before the world is updated, since the world
+    /// forgets about players as soon as they leave. Players are only announced
+    /// once both their entity and their tab list entry are known, since
+    /// friends are told apart by name.
+    pub(super) fn check_visual_range(&self, world: &WorldCache, packet: &ClientboundGamePacket) {
+        let config = self.config.get();
+        if !config.visual_range.enabled {
+            return;
+        }
+
+        let sighting = |player: &TrackedPlayer, name: String| PlayerSighting {
+            name,
+            uuid: player.uuid,
+            x: player.position.x,
+            y: player.position.y,
+            z: player.position.z,
+        };
+        let named = |player: TrackedPlayer| {
+            let name = world.player_name(&player.uuid)?;
+            Some(sighting(&player, name))
+        };
+
+        let mut entered = vec![];
+        let mut left = vec![];
+        match packet {
+            ClientboundGamePacket::AddPlayer(packet) => {
+                entered.extend(named(TrackedPlayer {
+                    uuid: packet.uuid,
+                    position: packet.position,
+                }));
+            }
+            // Players that spawned before their entry came in enter now
+            ClientboundGamePacket::PlayerInfoUpdate(packet) if packet.actions.add_player => {
+                for entry in &packet.entries {
+                    let uuid = entry.profile.uuid;
+                    if world.player_name(&uuid).is_some() || entry.profile.name.is_empty() {
+                        continue;
+                    }
+                    if let Some(player) = world.players().find(|player| player.uuid == uuid) {
+                        entered.push(sighting(&player, entry.profile.name.clone()));
+                    }
+                }
+            }
+            ClientboundGamePacket::RemoveEntities(packet) => {
+                let players = packet.entity_ids.iter().filter_map(|id| world.player(*id));
+                left.extend(players.filter_map(named));
+            }
+            ClientboundGamePacket::Login(_) | ClientboundGamePacket::Respawn(_) => {
+                left.extend(world.players().filter_map(named));
+            }
+            _ => {}
+        }
+
+        // Friends don't need to be announced
+        let is_stranger = |player: &PlayerSighting| !config.is_friend(&player.name);
+        let entered = entered.into_iter().filter(is_stranger);
+        let left = left.into_iter().filter(is_stranger);
+
+        let events = entered
+            .map(EventKind::PlayerEnteredRange)
+            .chain(left.map(EventKind::PlayerLeftRange));
+        for event in events {
+            info!("{event}");
+            self.events.emit(event);
+        }
+    }
+
+    /// Starts appending visual range events to the configured log file, if
+    /// there is one
+    pub fn spawn_visual_range_log(&self) {
+        let Some(path) = self.config.get().visual_range.log_file.clone() else {
+            return;
+        };
+
+        let events = self.events.clone();
+        tokio::spawn(async move {
+            if let Err(err) = log_to_file(events, &path).await {
+                error!("Failed to write to the visual range log: {err}");
+            }
+        });
+    }
+}
+
+/// Appends every visual range event to a file until the event bus closes
+async fn log_to_file(events: EventBus, path: &Path) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await?;
+
+    let mut receiver = events.subscribe();
+    loop {
+        let event = match receiver.recv().await {
+            Ok(event) => event,
+            Err(RecvError::Lagged(skipped)) => {
+                warn!("Visual range log skipped {skipped} events");
+                continue;
+            }
+            Err(RecvError::Closed) => return Ok(()),
+        };
+
+        if let EventKind::PlayerEnteredRange(_) | EventKind::PlayerLeftRange(_) = event.kind {
+            let line = format!("[{}] {}\n", event.time.to_rfc3339(), event.kind);
+            file.write_all(line.as_bytes()).await?;
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use azalea_chat::{text_component::TextComponent, FormattedText};
+use azalea_protocol::packets::game::{
+    clientbound_system_chat_packet::ClientboundSystemChatPacket, ClientboundGamePacket,
+};
+use std::str::SplitWhitespace;
+
+use crate::app::App;
+
+/// How many messages a chat search shows at most
+const SEARCH_LIMIT: usize = 10;
+
+const HELP: &[&str] = &[
+    "/proxy chat search <text> - search recent chat",
+    "/proxy chat from <player> - show recent messages from a player",
+    "/proxy control <player> - let another connected player control the bot",
+    "/proxy reload - load the config file again",
+    "/proxy replay <start|stop> - record the session for ReplayMod",
+    "/proxy server [name] - move the bot to another server",
+];
+
+impl App {
+    /// Runs a command sent by an attached client if it's meant for the proxy
+    /// and returns the lines to reply with
+    pub async fn run_command(&self, client: u64, command: &str) -> Option<Vec<String>> {
+        let mut args = command.split_whitespace();
+        if args.next() != Some("proxy") {
+            return None;
+        }
+
+        // Spectators can look around but not change anything
+        let subcommand = args.next();
+        let changes_something = match subcommand {
+            Some("control" | "reload" | "replay") => true,
+            Some("server") => args.clone().next().is_some(),
+            _ => false,
+        };
+        if changes_something && !self.is_in_charge(client) {
+            return Some(vec!["Only whoever controls the bot can do that".to_string()]);
+        }
+
+        let reply = match subcommand {
+            Some("chat") => self.chat_command(args),
+            Some("control") => self.control_command(client, args),
+            Some("reload") => self.reload_command().await,
+            Some("replay") => self.replay_command(args).await,
+            Some("server") => self.server_command(args).await,
+            _ => HELP.iter().map(|line| line.to_string()).collect(),
+        };
+
+        Some(reply)
+    }
+
+    fn chat_command(&self, mut args: SplitWhitespace) -> Vec<String> {
+        let subcommand = args.next();
+        let query = args.collect::<Vec<_>>().join(" ");
+        if query.is_empty() {
+            return vec!["Usage: /proxy chat <search|from> <query>".to_string()];
+        }
+
+        let messages = match subcommand {
+            Some("search") => {
+                let query = query.to_lowercase();
+                self.chat_log.search(SEARCH_LIMIT, |message| {
+                    message.message.to_lowercase().contains(&query)
+                })
+            }
+            Some("from") => self
+                .chat_log
+                .search(SEARCH_LIMIT, |message| message.is_from(&query)),
+            _ => return vec!["Usage: /proxy chat <search|from> <query>".to_string()],
+        };
+
+        if messages.is_empty() {
+            return vec!["No matching messages".to_string()];
+        }
+        messages.iter().map(ToString::to_string).collect()
+    }
+
+    fn control_command(&self, client: u64, mut args: SplitWhitespace) -> Vec<String> {
+        let Some(player) = args.next() else {
+            return vec!["Usage: /proxy control <player>".to_string()];
+        };
+
+        // Everyone hears about it if it worked
+        match self.hand_off(client, player) {
+            Ok(()) => vec![],
+            Err(err) => vec![err.to_string()],
+        }
+    }
+
+    async fn server_command(&self, mut args: SplitWhitespace<'_>) -> Vec<String> {
+        let Some(name) = args.next() else {
+            let mut servers = self
+                .config
+                .get()
+                .servers
+                .keys()
+                .cloned()
+                .collect::<Vec<_>>();
+            servers.sort();
+            servers.insert(0, "default".to_string());
+            return vec![
+                format!("The bot is on {}", self.server_name()),
+                format!("Servers: {}", servers.join(", ")),
+            ];
+        };
+
+        // Everyone hears about it if it worked
+        match self.switch_server(name).await {
+            Ok(()) => vec![],
+            Err(err) => vec![err.to_string()],
+        }
+    }
+
+    async fn reload_command(&self) -> Vec<String> {
+        match self.reload_config().await {
+            Ok(lines) => lines,
+            Err(err) => vec![format!("Failed to reload the config: {err}")],
+        }
+    }
+
+    async fn replay_command(&self, mut args: SplitWhitespace<'_>) -> Vec<String> {
+        let result = match args.next() {
+            Some("start") => self.start_recording().await.map(|_| {
+                self.store.update(|state| state.recording = true);
+                "Started recording".to_string()
+            }),
+            Some("stop") => self.stop_recording().await.map(|path| {
+                self.store.update(|state| state.recording = false);
+                format!("Saved the recording to {}", path.display())
+            }),
+            _ => return vec!["Usage: /proxy replay <start|stop>".to_string()],
+        };
+
+        match result {
+            Ok(line) => vec![line],
+            Err(err) => vec![err.to_string()],
+        }
+    }
+}
+
+/// A chat message from the proxy itself
+pub fn system_message(text: impl Into<String>) -> ClientboundGamePacket {
+    ClientboundSystemChatPacket {
+        content: FormattedText::Text(TextComponent::new(text.into())),
+        overlay: false,
+    }
+    .get()
+}
@@ -0,0 +1,286 @@
+use anyhow::{bail as yeet, Result};
+use azalea_chat::{text_component::TextComponent, FormattedText};
+use azalea_core::{BitSet, BlockPos, GameType, ResourceLocation};
+use azalea_nbt::Tag;
+use azalea_protocol::packets::game::{
+    clientbound_disconnect_packet::ClientboundDisconnectPacket,
+    clientbound_keep_alive_packet::ClientboundKeepAlivePacket,
+    clientbound_level_chunk_with_light_packet::{
+        ClientboundLevelChunkPacketData, ClientboundLevelChunkWithLightPacket,
+    },
+    clientbound_light_update_packet::ClientboundLightUpdatePacketData,
+    clientbound_login_packet::ClientboundLoginPacket,
+    clientbound_player_position_packet::{ClientboundPlayerPositionPacket, RelativeArguments},
+    clientbound_set_chunk_cache_center_packet::ClientboundSetChunkCacheCenterPacket,
+    clientbound_set_default_spawn_position_packet::ClientboundSetDefaultSpawnPositionPacket,
+    ClientboundGamePacket,
+};
+use std::time::Duration;
+use tracing::info;
+
+use crate::app::{
+    bot::{BotStatus, ClientConn},
+    commands::system_message,
+    App,
+};
+
+/// How often the client hears from us while waiting, so it doesn't time out
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How far around spawn the client gets empty chunks
+const CHUNK_RADIUS: i32 = 2;
+
+/// Limbo is as tall as the overworld used to be
+const SECTIONS: usize = 16;
+
+impl App {
+    /// Keeps a client in an empty world until the bot is on the server
+    ///
+    /// This gives up if the bot goes offline for good.
+    pub(super) async fn wait_in_limbo(&self, conn: &mut ClientConn) -> Result<()> {
+        let mut status = self.bot.subscribe_status();
+        if *status.borrow_and_update() == BotStatus::Online {
+            return Ok(());
+        }
+
+        info!("Waiting in limbo until the bot is online");
+        for packet in limbo_packets() {
+            self.send_game(conn, packet).await?;
+        }
+
+        let mut keep_alive = tokio::time::interval(KEEP_ALIVE_INTERVAL);
+        loop {
+            let message = match status.borrow_and_update().clone() {
+                BotStatus::Online => return Ok(()),
+                BotStatus::Offline => {
+                    let packet = ClientboundDisconnectPacket {
+                        reason: FormattedText::Text(TextComponent::new(
+                            "The bot went offline".to_string(),
+                        )),
+                    };
+                    self.send_game(conn, packet.get()).await?;
+                    yeet!("The bot went offline while the client was in limbo");
+                }
+                BotStatus::Connecting => "Connecting to the server...".to_string(),
+                BotStatus::Disconnected(reason) => format!(
+                    "Couldn't connect to the server ({reason}), trying again in {} seconds",
+                    self.config.get().bot.reconnect_delay
+                ),
+            };
+            self.send_game(conn, system_message(message)).await?;
+
+            // Nothing the client says matters until we're on the server
+            loop {
+                tokio::select! {
+                    changed = status.changed() => {
+                        changed?;
+                        break;
+                    }
+                    _ = keep_alive.tick() => {
+                        let packet = ClientboundKeepAlivePacket { id: 0 };
+                        self.send_game(conn, packet.get()).await?;
+                    }
+                    packet = conn.packets.recv() => {
+                        if packet.is_none() {
+                            yeet!("Lost connection to the client");
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    async fn send_game(&self, conn: &mut ClientConn, packet: ClientboundGamePacket) -> Result<()> {
+        conn.write.write(packet).await?;
+        Ok(())
+    }
+}
+
+/// Everything that puts a client into limbo, floating in the void
+fn limbo_packets() -> Vec<ClientboundGamePacket> {
+    let dimension = ResourceLocation::new("gooberproxy:limbo");
+    let mut packets = vec![
+        ClientboundLoginPacket {
+            player_id: 0,
+            hardcore: false,
+            game_type: GameType::Spectator,
+            previous_game_type: None,
+            levels: vec![dimension.clone()],
+            registry_holder: registry_holder(),
+            dimension_type: ResourceLocation::new("minecraft:overworld"),
+            dimension,
+            seed: 0,
+            max_players: 1,
+            chunk_radius: CHUNK_RADIUS as u32,
+            simulation_distance: CHUNK_RADIUS as u32,
+            reduced_debug_info: false,
+            show_death_screen: false,
+            is_debug: false,
+            is_flat: true,
+            last_death_location: None,
+        }
+        .get(),
+        ClientboundSetDefaultSpawnPositionPacket {
+            pos: BlockPos::new(0, 64, 0),
+            angle: 0.0,
+        }
+        .get(),
+        ClientboundSetChunkCacheCenterPacket { x: 0, z: 0 }.get(),
+    ];
+
+    for x in -CHUNK_RADIUS..=CHUNK_RADIUS {
+        for z in -CHUNK_RADIUS..=CHUNK_RADIUS {
+            packets.push(empty_chunk(x, z));
+        }
+    }
+
+    packets.push(
+        ClientboundPlayerPositionPacket {
+            x: 0.5,
+            y: 64.0,
+            z: 0.5,
+            y_rot: 0.0,
+            x_rot: 0.0,
+            relative_arguments: RelativeArguments {
+                x: false,
+                y: false,
+                z: false,
+                y_rot: false,
+                x_rot: false,
+            },
+            id: 0,
+            dismount_vehicle: false,
+        }
+        .get(),
+    );
+
+    packets
+}
+
+/// A chunk with nothing but air in it
+fn empty_chunk(x: i32, z: i32) -> ClientboundGamePacket {
+    // Every section is a block count of 0 followed by single-value palettes
+    // of air and the first biome
+    let data = vec![0; SECTIONS * 8];
+
+    ClientboundLevelChunkWithLightPacket {
+        x,
+        z,
+        chunk_data: ClientboundLevelChunkPacketData {
+            heightmaps: compound([]),
+            data,
+            block_entities: vec![],
+        },
+        light_data: ClientboundLightUpdatePacketData {
+            trust_edges: true,
+            sky_y_mask: BitSet::new(SECTIONS + 2),
+            block_y_mask: BitSet::new(SECTIONS + 2),
+            empty_sky_y_mask: BitSet::new(SECTIONS + 2),
+            empty_block_y_mask: BitSet::new(SECTIONS + 2),
+            sky_updates: vec![],
+            block_updates: vec![],
+        },
+    }
+    .get()
+}
+
+/// The bare minimum of registries the client needs to join a world
+fn registry_holder() -> Tag {
+    let dimension_type = compound([
+        ("piglin_safe", Tag::Byte(0)),
+        ("has_raids", Tag::Byte(0)),
+        ("monster_spawn_light_level", Tag::Int(0)),
+        ("monster_spawn_block_light_limit", Tag::Int(0)),
+        ("natural", Tag::Byte(0)),
+        ("ambient_light", Tag::Float(1.0)),
+        ("fixed_time", Tag::Long(6000)),
+        ("infiniburn", string("#minecraft:infiniburn_overworld")),
+        ("respawn_anchor_works", Tag::Byte(0)),
+        ("has_skylight", Tag::Byte(0)),
+        ("bed_works", Tag::Byte(0)),
+        ("effects", string("minecraft:the_end")),
+        ("min_y", Tag::Int(0)),
+        ("height", Tag::Int(SECTIONS as i32 * 16)),
+        ("logical_height", Tag::Int(SECTIONS as i32 * 16)),
+        ("coordinate_scale", Tag::Double(1.0)),
+        ("ultrawarm", Tag::Byte(0)),
+        ("has_ceiling", Tag::Byte(0)),
+    ]);
+
+    let biome = compound([
+        ("precipitation", string("none")),
+        ("temperature", Tag::Float(0.5)),
+        ("downfall", Tag::Float(0.5)),
+        (
+            "effects",
+            compound([
+                ("sky_color", Tag::Int(0)),
+                ("fog_color", Tag::Int(0)),
+                ("water_color", Tag::Int(0x3f76e4)),
+                ("water_fog_color", Tag::Int(0x050533)),
+            ]),
+        ),
+    ]);
+
+    let chat_type = compound([
+        ("chat", chat_decoration("chat.type.text")),
+        ("narration", chat_decoration("chat.type.text.narrate")),
+    ]);
+
+    compound([
+        (
+            "minecraft:dimension_type",
+            registry(
+                "minecraft:dimension_type",
+                "minecraft:overworld",
+                dimension_type,
+            ),
+        ),
+        (
+            "minecraft:worldgen/biome",
+            registry("minecraft:worldgen/biome", "minecraft:plains", biome),
+        ),
+        (
+            "minecraft:chat_type",
+            registry("minecraft:chat_type", "minecraft:chat", chat_type),
+        ),
+    ])
+}
+
+/// A registry with a single entry
+fn registry(kind: &str, name: &str, element: Tag) -> Tag {
+    compound([
+        ("type", string(kind)),
+        (
+            "value",
+            Tag::List(vec![compound([
+                ("name", string(name)),
+                ("id", Tag::Int(0)),
+                ("element", element),
+            ])]),
+        ),
+    ])
+}
+
+fn chat_decoration(translation_key: &str) -> Tag {
+    compound([
+        ("translation_key", string(translation_key)),
+        (
+            "parameters",
+            Tag::List(vec![string("sender"), string("content")]),
+        ),
+    ])
+}
+
+fn compound<const N: usize>(entries: [(&str, Tag); N]) -> Tag {
+    Tag::Compound(
+        entries
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect(),
+    )
+}
+
+fn string(value: &str) -> Tag {
+    Tag::String(value.to_string())
+}
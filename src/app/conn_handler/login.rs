@@ -1,24 +1,41 @@
-use anyhow::{bail as yeet, Context, Result};
+use anyhow::{anyhow, bail as yeet, Context, Result};
+use azalea_auth::game_profile::GameProfile;
 use azalea_chat::{text_component::TextComponent, FormattedText};
-use azalea_client::Account;
 use azalea_protocol::packets::login::{
-    clientbound_login_disconnect_packet::ClientboundLoginDisconnectPacket, ServerboundLoginPacket,
+    clientbound_game_profile_packet::ClientboundGameProfilePacket,
+    clientbound_hello_packet::ClientboundHelloPacket,
+    clientbound_login_compression_packet::ClientboundLoginCompressionPacket,
+    clientbound_login_disconnect_packet::ClientboundLoginDisconnectPacket,
+    serverbound_key_packet::NonceOrSaltSignature, ClientboundLoginPacket, ServerboundLoginPacket,
 };
 use tracing::{debug, info, warn};
 
 use crate::{
-    app::App,
+    app::{
+        bot::{BotStatus, Role},
+        commands::system_message,
+        App,
+    },
+    config::{AccountKind, Config},
     conn::ServerLoginConn,
-    join::{join_server, say_hello},
+    server_auth::{self, VerifiedProfile},
+    timeouts::{timely, Phase},
 };
 
+/// Packets bigger than this many bytes get compressed on their way to the
+/// player
+const COMPRESSION_THRESHOLD: i32 = 256;
+
 impl App {
     /// Handles a client that has specified it wants to log in
     pub async fn handle_login(&self, mut conn1: ServerLoginConn) -> Result<()> {
         info!("Handling login request");
 
         // Read the hello
-        let hello = match conn1.read().await.context("Failed to read login request")? {
+        let packet = timely(&self.config.get().timeouts, Phase::LoginHello, conn1.read())
+            .await?
+            .context("Failed to read login request")?;
+        let hello = match packet {
             ServerboundLoginPacket::Hello(hello) => hello,
             _ => {
                 yeet!("Expected hello");
@@ -26,59 +43,193 @@ impl App {
         };
         debug!("Hello: {:?}", hello);
 
-        // Perform a high-tech security check
-        if hello.username != self.config.player {
+        // Perform a high-tech security check, which is only a first guess
+        // until the client proves who it is
+        let config = self.config.get();
+        if role_of(&config, &hello.username).is_none() {
             warn!("Kicking unknown player {}", hello.username);
-            let kick_packet = ClientboundLoginDisconnectPacket {
-                reason: FormattedText::Text(TextComponent::new("goober".to_string())),
-            };
-            conn1.write(kick_packet.get()).await?;
-            return Ok(());
+            return self.kick(&mut conn1, "goober").await;
         }
 
-        // This sometimes is a laughing matter
-        let conn1 = conn1.unwrap()?; // ← will not panic → → → → → → → → → ↓
-        let conn2 = say_hello(&self.config.server_addr, hello).await?.unwrap()?;
+        let Some(profile) = self.verify_client(&mut conn1, &hello.username).await? else {
+            warn!("{} couldn't prove who they are", hello.username);
+            return self.kick(&mut conn1, "Failed to verify username!").await;
+        };
+        let Some(role) = role_of(&config, &profile.name) else {
+            warn!("Kicking unknown player {}", profile.name);
+            return self.kick(&mut conn1, "goober").await;
+        };
 
-        // This is not a laughing matter anymore
-        let (mut conn1_read, mut conn1_write) = tokio::io::split(conn1);
-        let (mut conn2_read, mut conn2_write) = tokio::io::split(conn2);
+        // Make sure the player knows why the bot isn't online anymore before
+        // letting them in again
+        if role == Role::Controller {
+            if let Some(trigger) = self.bot.acknowledge() {
+                self.store.update(|state| state.tripped = None);
+                info!("Telling the player about the safety trigger");
+                let reason =
+                    format!("The bot was logged out because it {trigger}. Rejoin to continue.");
+                return self.kick(&mut conn1, &reason).await;
+            }
 
-        info!("Relaying traffic");
+            if !self.bot.is_running() {
+                self.start_bot();
+            }
+        } else if self.bot.status() == BotStatus::Offline {
+            return self.kick(&mut conn1, "The bot is offline").await;
+        }
+
+        // Controllers play as whoever the bot joins as, spectators as
+        // themselves
+        let game_profile = match role {
+            Role::Controller => self.bot_profile().await?,
+            Role::Spectator => GameProfile::new(profile.id, profile.name.clone()),
+        };
 
-        // A highly sophisticated proprietary algorithm that determines who ended the
-        // connection
-        let who_disconnected = tokio::select! {
-            _ = tokio::io::copy(&mut conn1_read, &mut conn2_write) => WhoDisconnected::Client,
-            _ = tokio::io::copy(&mut conn2_read, &mut conn1_write) => WhoDisconnected::Server,
+        let compression = ClientboundLoginCompressionPacket {
+            compression_threshold: COMPRESSION_THRESHOLD,
         };
+        self.send_login(&mut conn1, compression.get()).await?;
+        conn1.set_compression_threshold(COMPRESSION_THRESHOLD);
+        self.send_login(
+            &mut conn1,
+            ClientboundGameProfilePacket { game_profile }.get(),
+        )
+        .await?;
+        let mut conn1 = self.client_conn(conn1.game());
+
+        // The bot might still be connecting, so the player has to wait
+        self.wait_in_limbo(&mut conn1).await?;
 
-        info!("{who_disconnected:?} disconnected");
+        // Tell the player about everything they missed
+        let mut backlog = vec![];
+        if role == Role::Controller {
+            if let Some(away_since) = self.bot.come_back() {
+                backlog.extend(self.missed_chat(away_since));
+            }
+            let whispers = self.bot.take_whispers();
+            if !whispers.is_empty() {
+                backlog.push(system_message(format!(
+                    "You got {} whispers while you were away:",
+                    whispers.len()
+                )));
+                backlog.extend(
+                    whispers
+                        .iter()
+                        .map(|message| system_message(message.to_string())),
+                );
+            }
+        }
 
-        // Perform the funny (why people would use this in the first place)
-        if who_disconnected == WhoDisconnected::Client {
-            let account = Account::microsoft(&self.config.account).await?;
-            let (mut conn, profile) = join_server(&self.config.server_addr, &account).await?;
+        self.attach(&profile.name, role, conn1, backlog).await
+    }
 
-            info!("Successfully reconnected as {}", profile.name);
+    /// Who the bot is or will be once it's on the server
+    async fn bot_profile(&self) -> Result<GameProfile> {
+        if let Some(profile) = self.bot.profile() {
+            return Ok(profile);
+        }
 
-            // Yeet the connection off an airplane transporting metal pipes
-            let app_clone = self.clone();
-            tokio::spawn(async move {
-                if let Err(err) = app_clone.keep_alive(&mut conn).await {
-                    warn!("Keep alive task disconnected: {err}");
-                }
-            });
+        // Offline accounts get their UUID from the server, which makes it up
+        // the same way
+        let config = self.config.get();
+        if config.account_kind == AccountKind::Offline {
+            let uuid = azalea_auth::offline::generate_uuid(&config.account);
+            return Ok(GameProfile::new(uuid, config.account.clone()));
         }
 
+        // The bot is probably logging in right now, and the player would time
+        // out waiting for that, so go by who it was last time
+        if let Some(auth) = self.store.get().auth {
+            if let (true, Some(uuid)) = (auth.account == config.account, auth.uuid) {
+                return Ok(GameProfile::new(uuid, auth.username));
+            }
+        }
+
+        // Nobody knows who the account is yet, so wait for the bot's login
+        let account = self.authenticate().await?;
+        let uuid = account
+            .uuid
+            .unwrap_or_else(|| azalea_auth::offline::generate_uuid(&account.username));
+        Ok(GameProfile::new(uuid, account.username))
+    }
+
+    /// Makes the client prove it owns the account it logs in as, turning on
+    /// encryption on the way, and returns who Mojang says it is
+    async fn verify_client(
+        &self,
+        conn: &mut ServerLoginConn,
+        username: &str,
+    ) -> Result<Option<VerifiedProfile>> {
+        let nonce: [u8; 4] = rand::random();
+        let request = ClientboundHelloPacket {
+            server_id: String::new(),
+            public_key: self.server_key.public_der().to_vec(),
+            nonce: nonce.to_vec(),
+        };
+        self.send_login(conn, request.get()).await?;
+
+        let packet = timely(
+            &self.config.get().timeouts,
+            Phase::EncryptionResponse,
+            conn.read(),
+        )
+        .await?
+        .context("Failed to read the encryption response")?;
+        let ServerboundLoginPacket::Key(response) = packet else {
+            yeet!("Expected an encryption response");
+        };
+
+        // Only a client that got our key can send the nonce back
+        let encrypted_nonce = match &response.nonce_or_salt_signature {
+            NonceOrSaltSignature::Nonce(nonce) => nonce,
+            NonceOrSaltSignature::SaltSignature(_) => {
+                yeet!("Expected the nonce in the encryption response")
+            }
+        };
+        if self.server_key.decrypt(encrypted_nonce)? != nonce {
+            yeet!("The client sent back the wrong nonce");
+        }
+        let secret: [u8; 16] = self
+            .server_key
+            .decrypt(&response.key_bytes)?
+            .try_into()
+            .map_err(|_| anyhow!("The shared secret isn't 16 bytes"))?;
+        conn.set_encryption_key(secret);
+
+        match server_auth::has_joined(username, &secret, self.server_key.public_der()).await {
+            Ok(profile) => Ok(profile),
+            Err(err) => {
+                warn!("Couldn't ask Mojang about {username}: {err}");
+                Ok(None)
+            }
+        }
+    }
+
+    /// Refuses to let a player in, telling them why
+    async fn kick(&self, conn: &mut ServerLoginConn, reason: &str) -> Result<()> {
+        let kick_packet = ClientboundLoginDisconnectPacket {
+            reason: FormattedText::Text(TextComponent::new(reason.to_string())),
+        };
+        self.send_login(conn, kick_packet.get()).await
+    }
+
+    async fn send_login(
+        &self,
+        conn: &mut ServerLoginConn,
+        packet: ClientboundLoginPacket,
+    ) -> Result<()> {
+        conn.write(packet).await?;
         Ok(())
     }
 }
 
-/// Returned by a highly sophisticated proprietary algorithm that determines who
-/// ended the connection
-#[derive(Debug, PartialEq, Eq)]
-enum WhoDisconnected {
-    Client,
-    Server,
+/// Whether someone with this name may join, and as who
+fn role_of(config: &Config, name: &str) -> Option<Role> {
+    if name == config.player {
+        Some(Role::Controller)
+    } else if config.spectators.iter().any(|spectator| spectator == name) {
+        Some(Role::Spectator)
+    } else {
+        None
+    }
 }
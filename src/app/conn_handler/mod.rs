@@ -1,13 +1,17 @@
 use anyhow::{bail as yeet, Context, Result};
-use azalea_protocol::{
-    connect::Connection,
-    packets::{handshake::ServerboundHandshakePacket, ConnectionProtocol as HandshakeIntention},
+use azalea_protocol::packets::{
+    handshake::ServerboundHandshakePacket, ConnectionProtocol as HandshakeIntention,
 };
 use tokio::net::TcpStream;
 use tracing::{debug, info};
 
-use crate::{app::App, conn::ServerHandshakeConn};
+use crate::{
+    app::App,
+    conn::{Connection, ServerHandshakeConn},
+    timeouts::{timely, Phase},
+};
 
+mod limbo;
 mod login;
 mod status;
 
@@ -19,11 +23,14 @@ impl App {
         info!("Accepted connection");
 
         // This is a laughing matter
-        let mut conn: ServerHandshakeConn = Connection::wrap(socket);
+        let mut conn: ServerHandshakeConn =
+            Connection::wrap(socket).captured(self.capture.as_ref());
 
         // Read the handshake, determine what to do with it and do it
-        let ServerboundHandshakePacket::ClientIntention(handshake) =
-            conn.read().await.context("Failed to read handshake")?;
+        let packet = timely(&self.config.get().timeouts, Phase::Handshake, conn.read())
+            .await?
+            .context("Failed to read handshake")?;
+        let ServerboundHandshakePacket::ClientIntention(handshake) = packet;
         debug!("Handshake: {:?}", handshake);
         match handshake.intention {
             HandshakeIntention::Status => {
@@ -1,5 +1,4 @@
 use anyhow::{bail as yeet, Context, Result};
-use azalea_chat::{text_component::TextComponent, FormattedText};
 use azalea_protocol::packets::{
     status::{
         clientbound_pong_response_packet::ClientboundPongResponsePacket,
@@ -12,7 +11,11 @@ use azalea_protocol::packets::{
 };
 use tracing::info;
 
-use crate::{app::App, conn::ServerStatusConn};
+use crate::{
+    app::App,
+    conn::ServerStatusConn,
+    timeouts::{timely, Phase},
+};
 
 impl App {
     /// Handles a client that has specified it wants to receive a status
@@ -21,7 +24,14 @@ impl App {
         info!("Handling status request");
 
         // Read the request
-        let _ = match conn.read().await.context("Failed to read status request")? {
+        let packet = timely(
+            &self.config.get().timeouts,
+            Phase::StatusRequest,
+            conn.read(),
+        )
+        .await?
+        .context("Failed to read status request")?;
+        let _ = match packet {
             ServerboundStatusPacket::StatusRequest(request) => request,
             _ => {
                 yeet!("Expected status request");
@@ -30,9 +40,7 @@ impl App {
 
         // Send the response
         let status_response = ClientboundStatusResponsePacket {
-            description: FormattedText::Text(TextComponent::new(
-                "Goobers Inc. secret test server (real)".to_string(),
-            )),
+            description: self.config.get().motd.clone(),
             favicon: None,
             players: StatusPlayers {
                 max: 420,
@@ -46,12 +54,16 @@ impl App {
             previews_chat: None,
             enforces_secure_chat: None,
         };
-        conn.write(status_response.get())
+        let status_response = status_response.get();
+        conn.write(status_response)
             .await
             .context("Failed to write status response")?;
 
         // Read the request
-        let ping_request = match conn.read().await.context("Failed to read ping request")? {
+        let packet = timely(&self.config.get().timeouts, Phase::PingRequest, conn.read())
+            .await?
+            .context("Failed to read ping request")?;
+        let ping_request = match packet {
             ServerboundStatusPacket::PingRequest(ping_request) => ping_request,
             _ => {
                 yeet!("Expected ping request");
@@ -62,7 +74,8 @@ impl App {
         let ping_response = ClientboundPongResponsePacket {
             time: ping_request.time,
         };
-        conn.write(ping_response.get())
+        let ping_response = ping_response.get();
+        conn.write(ping_response)
             .await
             .context("Failed to write pong response")?;
 
@@ -1,33 +1,103 @@
 use anyhow::{Context, Result};
+use std::sync::Arc;
 use tokio::net::TcpListener;
 use tracing::info;
 
-use crate::config::Config;
+use crate::{
+    capture::Capture, chat_log::ChatLog, config::SharedConfig, events::EventBus,
+    limits::ConnectionLimits, server_auth::ServerKey, store::StateStore,
+};
 
+use self::bot::BotControl;
+
+pub use self::bot::SafetyTrigger;
+
+mod bot;
+mod commands;
 mod conn_handler;
-mod keep_alive;
+mod reload;
+mod shutdown;
 
 #[derive(Clone)]
 pub struct App {
-    pub config: Config,
+    pub config: SharedConfig,
+    pub bot: Arc<BotControl>,
+    pub events: EventBus,
+    pub chat_log: Arc<ChatLog>,
+
+    /// Where packets are captured to, if anywhere
+    pub capture: Option<Capture>,
+
+    pub limits: Arc<ConnectionLimits>,
+
+    /// What's remembered across restarts
+    pub store: Arc<StateStore>,
+
+    /// What players log in with, so they have to prove who they are
+    pub server_key: Arc<ServerKey>,
 }
 
 impl App {
-    /// Initializes the app state (currently it only loads the config)
-    pub async fn init(config: Config) -> Result<Self> {
-        Ok(Self { config })
+    /// Initializes the app state
+    pub async fn init(config: SharedConfig) -> Result<Self> {
+        let current = config.get();
+        let chat_log = ChatLog::open(&current.chat_log)
+            .await
+            .context("Failed to open the chat log")?;
+
+        let capture = match &current.capture.file {
+            Some(path) => Some(
+                Capture::create(path)
+                    .await
+                    .context("Failed to start the packet capture")?,
+            ),
+            None => None,
+        };
+        let limits = ConnectionLimits::new(&current.limits);
+        let store = StateStore::open(&current.data_dir)
+            .await
+            .context("Failed to load the saved state")?;
+        let server_key = tokio::task::spawn_blocking(ServerKey::generate)
+            .await?
+            .context("Failed to generate the server key")?;
+
+        Ok(Self {
+            config,
+            bot: Arc::default(),
+            events: EventBus::new(),
+            chat_log: Arc::new(chat_log),
+            capture,
+            limits: Arc::new(limits),
+            store: Arc::new(store),
+            server_key: Arc::new(server_key),
+        })
     }
 
     /// The app's entrypoint with the config already loaded
     pub async fn run(&mut self) -> Result<()> {
-        let listener = TcpListener::bind(&self.config.listen_addr)
+        let listener = TcpListener::bind(&self.config.get().listen_addr)
             .await
             .context("Failed to bind to socket")?;
 
+        self.spawn_visual_range_log();
+        self.spawn_notifications();
+        self.spawn_api();
+        self.spawn_state_saver();
+        self.restore_session();
+        self.spawn_config_watcher();
+
         info!("Listening on {}", listener.local_addr()?);
-        self.listen_for_connections(listener)
-            .await
-            .context("Failed to listen for connections (what)")?;
+        tokio::select! {
+            result = self.listen_for_connections(listener) => {
+                result.context("Failed to listen for connections (what)")?;
+            }
+            result = self.wait_for_shutdown_signal() => {
+                result.context("Failed to listen for signals")?;
+            }
+        }
+
+        // Stop accepting connections before wrapping up
+        self.shut_down().await;
 
         Ok(())
     }
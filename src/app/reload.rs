@@ -0,0 +1,94 @@
+use anyhow::Result;
+use std::time::{Duration, SystemTime};
+use tracing::{info, warn};
+
+use crate::app::App;
+
+/// How often the config file is checked for changes
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+impl App {
+    /// Reloads the config whenever the file changes or the process gets a
+    /// SIGHUP
+    pub(super) fn spawn_config_watcher(&self) {
+        let app = self.clone();
+        tokio::spawn(async move {
+            let mut modified = app.config_modified().await;
+            let mut interval = tokio::time::interval(WATCH_INTERVAL);
+            let mut hangup = hangup_signal();
+
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {
+                        let now = app.config_modified().await;
+                        if now == modified {
+                            continue;
+                        }
+                        modified = now;
+                    }
+                    Some(()) = recv(&mut hangup) => info!("Got SIGHUP"),
+                }
+
+                if let Err(err) = app.reload_config().await {
+                    warn!("Failed to reload the config, keeping the old one: {err}");
+                }
+            }
+        });
+    }
+
+    /// Starts using whatever is in the config file now and returns what the
+    /// player should know about it
+    pub async fn reload_config(&self) -> Result<Vec<String>> {
+        let restart_required = self.config.reload().await?;
+        info!("Reloaded the config");
+
+        let mut lines = vec!["Reloaded the config".to_string()];
+        if !restart_required.is_empty() {
+            let changed = restart_required.join(", ");
+            warn!("Changes to {changed} only take effect after a restart");
+            lines.push(format!("Restart the proxy to apply changes to {changed}"));
+        }
+        Ok(lines)
+    }
+
+    async fn config_modified(&self) -> Option<SystemTime> {
+        let metadata = tokio::fs::metadata(self.config.path()).await.ok()?;
+        metadata.modified().ok()
+    }
+}
+
+#[cfg(unix)]
+type Hangup = Option<tokio::signal::unix::Signal>;
+
+#[cfg(not(unix))]
+type Hangup = ();
+
+#[cfg(unix)]
+fn hangup_signal() -> Hangup {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    match signal(SignalKind::hangup()) {
+        Ok(signal) => Some(signal),
+        Err(err) => {
+            warn!("Can't listen for SIGHUP: {err}");
+            None
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn hangup_signal() -> Hangup {}
+
+/// Waits for the next SIGHUP, or forever if there's no way to get one
+#[cfg(unix)]
+async fn recv(hangup: &mut Hangup) -> Option<()> {
+    match hangup {
+        Some(signal) => signal.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(not(unix))]
+async fn recv(_: &mut Hangup) -> Option<()> {
+    std::future::pending().await
+}
@@ -0,0 +1,53 @@
+use anyhow::Result;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::app::App;
+
+impl App {
+    /// Waits until the process is asked to stop
+    pub(super) async fn wait_for_shutdown_signal(&self) -> Result<()> {
+        #[cfg(unix)]
+        {
+            use tokio::signal::unix::{signal, SignalKind};
+
+            let mut terminate = signal(SignalKind::terminate())?;
+            tokio::select! {
+                result = tokio::signal::ctrl_c() => result?,
+                _ = terminate.recv() => {}
+            }
+        }
+
+        #[cfg(not(unix))]
+        tokio::signal::ctrl_c().await?;
+
+        Ok(())
+    }
+
+    /// Kicks everyone, takes the bot off the server and writes out everything
+    /// that's still buffered, giving up once the deadline has passed
+    pub(super) async fn shut_down(&self) {
+        info!("Shutting down");
+
+        let deadline = Duration::from_secs(self.config.get().shutdown.deadline);
+        if tokio::time::timeout(deadline, self.wrap_up())
+            .await
+            .is_err()
+        {
+            warn!("Didn't finish shutting down in time, exiting anyway");
+        }
+    }
+
+    async fn wrap_up(&self) {
+        self.stop_bot(&self.config.get().shutdown.message).await;
+
+        // Clients get disconnected once everything before the kick has been
+        // sent to them
+        self.limits.wait_until_idle().await;
+
+        if let Err(err) = self.chat_log.flush().await {
+            warn!("Failed to flush the chat log: {err}");
+        }
+        self.save_state().await;
+    }
+}
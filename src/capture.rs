@@ -0,0 +1,193 @@
+use anyhow::{bail as yeet, Result};
+use std::{fmt, path::Path, time::Instant};
+use tokio::{
+    fs::File,
+    io::{AsyncWriteExt, BufWriter},
+    sync::mpsc::{self, UnboundedReceiver, UnboundedSender},
+};
+use tracing::error;
+
+/// The first bytes of every capture file, followed by the format version
+const MAGIC: &[u8] = b"GOOBCAP";
+const VERSION: u8 = 1;
+
+/// Which way a packet was going, and between whom
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+#[repr(u8)]
+pub enum Direction {
+    ToServer = 0,
+    FromServer = 1,
+    ToClient = 2,
+    FromClient = 3,
+}
+
+impl Direction {
+    /// Whether the packet was on its way to a client
+    pub fn is_clientbound(self) -> bool {
+        matches!(self, Self::FromServer | Self::ToClient)
+    }
+}
+
+/// The state a connection was in when a packet was sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+#[repr(u8)]
+pub enum State {
+    Handshake = 0,
+    Status = 1,
+    Login = 2,
+    Game = 3,
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let arrow = match self {
+            Self::ToServer => "proxy -> server",
+            Self::FromServer => "server -> proxy",
+            Self::ToClient => "proxy -> client",
+            Self::FromClient => "client -> proxy",
+        };
+        f.pad(arrow)
+    }
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Self::Handshake => "handshake",
+            Self::Status => "status",
+            Self::Login => "login",
+            Self::Game => "game",
+        };
+        f.pad(name)
+    }
+}
+
+/// A packet read back from a capture file
+#[derive(Debug)]
+pub struct CapturedPacket {
+    /// Milliseconds since the capture was started
+    pub time: u32,
+    pub direction: Direction,
+    pub state: State,
+
+    /// The packet id followed by the packet, like it's sent over the wire
+    /// before compression and encryption
+    pub data: Vec<u8>,
+}
+
+/// Writes every packet crossing the proxy to a file
+///
+/// Each packet is stored as its time in milliseconds (u32), direction (u8),
+/// state (u8), length (u32) and then the packet id and data, all big endian.
+#[derive(Debug, Clone)]
+pub struct Capture {
+    started: Instant,
+    sender: UnboundedSender<CapturedPacket>,
+}
+
+impl Capture {
+    /// Starts capturing into a file, replacing whatever was in it
+    pub async fn create(path: &Path) -> Result<Self> {
+        let mut file = BufWriter::new(File::create(path).await?);
+        file.write_all(MAGIC).await?;
+        file.write_u8(VERSION).await?;
+
+        let (sender, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            if let Err(err) = write_packets(file, receiver).await {
+                error!("Failed to write to the packet capture: {err}");
+            }
+        });
+
+        Ok(Self {
+            started: Instant::now(),
+            sender,
+        })
+    }
+
+    /// Records the packet id and data of a frame going in `direction`
+    pub fn record(&self, direction: Direction, state: State, data: &[u8]) {
+        let _ = self.sender.send(CapturedPacket {
+            time: self.started.elapsed().as_millis() as u32,
+            direction,
+            state,
+            data: data.to_vec(),
+        });
+    }
+}
+
+async fn write_packets(
+    mut file: BufWriter<File>,
+    mut receiver: UnboundedReceiver<CapturedPacket>,
+) -> Result<()> {
+    while let Some(packet) = receiver.recv().await {
+        write_packet(&mut file, packet).await?;
+        while let Ok(packet) = receiver.try_recv() {
+            write_packet(&mut file, packet).await?;
+        }
+
+        // Whatever got the proxy kicked is usually the last thing in the file,
+        // so don't keep it in the buffer
+        file.flush().await?;
+    }
+    Ok(())
+}
+
+async fn write_packet(file: &mut BufWriter<File>, packet: CapturedPacket) -> Result<()> {
+    file.write_u32(packet.time).await?;
+    file.write_u8(packet.direction as u8).await?;
+    file.write_u8(packet.state as u8).await?;
+    file.write_u32(packet.data.len() as u32).await?;
+    file.write_all(&packet.data).await?;
+    Ok(())
+}
+
+/// Reads every packet from a capture file
+pub fn read_capture(mut bytes: &[u8]) -> Result<Vec<CapturedPacket>> {
+    let Some(rest) = bytes.strip_prefix(MAGIC) else {
+        yeet!("Not a packet capture");
+    };
+    match rest.first() {
+        Some(&VERSION) => bytes = &rest[1..],
+        Some(version) => yeet!("Unsupported capture version {version}"),
+        None => yeet!("Not a packet capture"),
+    }
+
+    let mut packets = vec![];
+    while !bytes.is_empty() {
+        if bytes.len() < 10 {
+            yeet!("Capture ends in the middle of a packet");
+        }
+        let (header, rest) = bytes.split_at(10);
+        let time = u32::from_be_bytes(header[0..4].try_into()?);
+        let direction = match header[4] {
+            0 => Direction::ToServer,
+            1 => Direction::FromServer,
+            2 => Direction::ToClient,
+            3 => Direction::FromClient,
+            direction => yeet!("Unknown direction {direction}"),
+        };
+        let state = match header[5] {
+            0 => State::Handshake,
+            1 => State::Status,
+            2 => State::Login,
+            3 => State::Game,
+            state => yeet!("Unknown state {state}"),
+        };
+        let length = u32::from_be_bytes(header[6..10].try_into()?) as usize;
+
+        if rest.len() < length {
+            yeet!("Capture ends in the middle of a packet");
+        }
+        let (data, rest) = rest.split_at(length);
+        packets.push(CapturedPacket {
+            time,
+            direction,
+            state,
+            data: data.to_vec(),
+        });
+        bytes = rest;
+    }
+
+    Ok(packets)
+}
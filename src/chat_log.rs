@@ -0,0 +1,240 @@
+use anyhow::Result;
+use azalea_protocol::packets::game::ClientboundGamePacket;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::{collections::VecDeque, fmt, sync::Mutex};
+use tokio::{
+    fs::{File, OpenOptions},
+    io::AsyncWriteExt,
+};
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::{
+    app::{commands::system_message, App},
+    config::ChatLogConfig,
+    events::EventKind,
+};
+
+/// A chat message the server sent us
+#[derive(Debug, Clone, Serialize)]
+pub struct ChatMessage {
+    pub time: DateTime<Utc>,
+    pub kind: ChatKind,
+
+    /// The UUID of the player that sent the message, if the server told us
+    pub sender: Option<Uuid>,
+
+    /// The name of whoever sent the message, if there is one
+    pub sender_name: Option<String>,
+
+    /// The message as the player would see it in chat
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChatKind {
+    Player,
+    System,
+    Disguised,
+}
+
+impl ChatMessage {
+    /// Extracts a chat message from a packet, if it is one
+    pub fn from_packet(packet: &ClientboundGamePacket) -> Option<Self> {
+        let (kind, sender, sender_name, message) = match packet {
+            ClientboundGamePacket::PlayerChat(packet) => (
+                ChatKind::Player,
+                Some(packet.sender),
+                Some(packet.chat_type.name.to_string()),
+                packet.message().to_string(),
+            ),
+            ClientboundGamePacket::DisguisedChat(packet) => (
+                ChatKind::Disguised,
+                None,
+                Some(packet.chat_type.name.to_string()),
+                packet.message.to_string(),
+            ),
+            // Overlay messages end up in the action bar, which some servers
+            // update every tick
+            ClientboundGamePacket::SystemChat(packet) if !packet.overlay => {
+                (ChatKind::System, None, None, packet.content.to_string())
+            }
+            _ => return None,
+        };
+
+        Some(Self {
+            time: Utc::now(),
+            kind,
+            sender,
+            sender_name,
+            message,
+        })
+    }
+
+    /// Whether the message was sent by a player with this name or UUID
+    pub fn is_from(&self, sender: &str) -> bool {
+        let name_matches = self
+            .sender_name
+            .as_ref()
+            .map_or(false, |name| name.eq_ignore_ascii_case(sender));
+        let uuid_matches = self
+            .sender
+            .map_or(false, |uuid| uuid.to_string() == sender.to_lowercase());
+        name_matches || uuid_matches
+    }
+}
+
+impl fmt::Display for ChatMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[{}] {}",
+            self.time.format("%Y-%m-%d %H:%M:%S"),
+            self.message
+        )
+    }
+}
+
+/// Keeps every chat message in an append-only file and the most recent ones in
+/// memory
+#[derive(Debug)]
+pub struct ChatLog {
+    recent: Mutex<VecDeque<ChatMessage>>,
+    capacity: usize,
+    file: Option<tokio::sync::Mutex<File>>,
+}
+
+impl ChatLog {
+    /// Opens the log file for appending
+    pub async fn open(config: &ChatLogConfig) -> Result<Self> {
+        let file = match &config.file {
+            Some(path) => Some(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .await?,
+            ),
+            None => None,
+        };
+
+        Ok(Self {
+            recent: Mutex::new(VecDeque::with_capacity(config.recent)),
+            capacity: config.recent,
+            file: file.map(tokio::sync::Mutex::new),
+        })
+    }
+
+    /// Makes sure everything has made it to the log file
+    pub async fn flush(&self) -> Result<()> {
+        if let Some(file) = &self.file {
+            file.lock().await.flush().await?;
+        }
+        Ok(())
+    }
+
+    /// Remembers a message and writes it to the log file
+    pub async fn record(&self, message: ChatMessage) -> Result<()> {
+        let line = serde_json::to_string(&message)? + "\n";
+
+        {
+            let mut recent = self.recent.lock().unwrap();
+            if recent.len() >= self.capacity {
+                recent.pop_front();
+            }
+            if self.capacity > 0 {
+                recent.push_back(message);
+            }
+        }
+
+        if let Some(file) = &self.file {
+            file.lock().await.write_all(line.as_bytes()).await?;
+        }
+
+        Ok(())
+    }
+
+    /// The most recent messages that match `filter`, oldest first
+    pub fn search(&self, limit: usize, filter: impl Fn(&ChatMessage) -> bool) -> Vec<ChatMessage> {
+        let recent = self.recent.lock().unwrap();
+        let mut matches: Vec<_> = recent
+            .iter()
+            .rev()
+            .filter(|message| filter(message))
+            .take(limit)
+            .cloned()
+            .collect();
+        matches.reverse();
+        matches
+    }
+
+    /// Whether every message since `since` is still in memory, which isn't
+    /// the case once older ones had to make room
+    pub fn has_all_since(&self, since: DateTime<Utc>) -> bool {
+        let recent = self.recent.lock().unwrap();
+        recent.len() < self.capacity || recent.front().map_or(true, |oldest| oldest.time < since)
+    }
+}
+
+impl App {
+    /// Records a packet from the server in the chat log if it's a chat message
+    pub async fn record_chat(&self, packet: &ClientboundGamePacket) -> Option<ChatMessage> {
+        let message = ChatMessage::from_packet(packet)?;
+        if let Err(err) = self.chat_log.record(message.clone()).await {
+            warn!("Failed to write to the chat log: {err}");
+        }
+        self.events.emit(EventKind::Chat {
+            message: message.clone(),
+        });
+        Some(message)
+    }
+
+    /// Summarizes the chat the player missed since they left as system
+    /// messages, within the limits of the config
+    pub fn missed_chat(&self, since: DateTime<Utc>) -> Vec<ClientboundGamePacket> {
+        let config = self.config.get();
+        let replay = &config.chat_replay;
+        if !replay.enabled {
+            return vec![];
+        }
+
+        let missed = self
+            .chat_log
+            .search(usize::MAX, |message| message.time >= since);
+        if missed.is_empty() {
+            return vec![];
+        }
+
+        let cutoff = Utc::now() - chrono::Duration::seconds(replay.max_age as i64);
+        let recent: Vec<_> = missed
+            .iter()
+            .filter(|message| message.time >= cutoff)
+            .collect();
+        let shown = &recent[recent.len().saturating_sub(replay.max_messages)..];
+
+        // Older messages could have fallen out of memory already
+        let count = if self.chat_log.has_all_since(since) {
+            missed.len().to_string()
+        } else {
+            format!("at least {}", missed.len())
+        };
+        let header = if shown.len() < missed.len() {
+            format!(
+                "You missed {count} chat messages, here are the last {}:",
+                shown.len()
+            )
+        } else {
+            format!("You missed {count} chat messages:")
+        };
+
+        let mut packets = vec![system_message(header)];
+        packets.extend(
+            shown
+                .iter()
+                .map(|message| system_message(message.to_string())),
+        );
+        packets
+    }
+}
@@ -0,0 +1,92 @@
+use anyhow::{bail as yeet, Result};
+use toml::{Table, Value};
+
+/// What environment variables that override the config start with
+const PREFIX: &str = "GOOBERPROXY_";
+
+/// Overrides settings with `GOOBERPROXY_*` environment variables, where `__`
+/// goes one section deeper, like `GOOBERPROXY_BOT__RECONNECT_DELAY=30`
+///
+/// Values are read as TOML if they can be and as strings otherwise, so
+/// `'["a", "b"]'` is a list and `127.0.0.1:25565` is just a string.
+pub fn apply_overrides(
+    table: &mut Table,
+    vars: impl IntoIterator<Item = (String, String)>,
+) -> Result<()> {
+    for (name, value) in vars {
+        let Some(key) = name.strip_prefix(PREFIX) else {
+            continue;
+        };
+        let path = key.to_lowercase();
+        let mut path = path.split("__").peekable();
+
+        let mut section = &mut *table;
+        while let Some(key) = path.next() {
+            if key.is_empty() {
+                yeet!("{name} has an empty section in it");
+            }
+
+            if path.peek().is_none() {
+                section.insert(key.to_string(), parse_value(&value));
+                break;
+            }
+
+            let next = section
+                .entry(key.to_string())
+                .or_insert_with(|| Value::Table(Table::new()));
+            let Value::Table(next) = next else {
+                yeet!("{name} goes into `{key}`, which isn't a section");
+            };
+            section = next;
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_value(value: &str) -> Value {
+    toml::from_str::<Table>(&format!("value = {value}"))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| Value::String(value.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(vars: &[(&str, &str)]) -> Result<Table> {
+        let mut table: Table =
+            toml::from_str("player = \"goober\"\n[api]\nenabled = true").unwrap();
+        let vars = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()));
+        apply_overrides(&mut table, vars)?;
+        Ok(table)
+    }
+
+    #[test]
+    fn overrides_settings() {
+        let table = apply(&[
+            ("GOOBERPROXY_PLAYER", "someone"),
+            ("GOOBERPROXY_BOT__RECONNECT_DELAY", "30"),
+            ("GOOBERPROXY_API__TOKENS", r#"["a", "b"]"#),
+            ("GOOBERPROXY_SERVER_ADDR", "127.0.0.1:25565"),
+            ("HOME", "/root"),
+        ])
+        .unwrap();
+
+        assert_eq!(table["player"].as_str(), Some("someone"));
+        assert_eq!(table["bot"]["reconnect_delay"].as_integer(), Some(30));
+        assert_eq!(table["api"]["enabled"].as_bool(), Some(true));
+        assert_eq!(table["api"]["tokens"].as_array().unwrap().len(), 2);
+        assert_eq!(table["server_addr"].as_str(), Some("127.0.0.1:25565"));
+        assert!(!table.contains_key("home"));
+    }
+
+    #[test]
+    fn rejects_bad_paths() {
+        assert!(apply(&[("GOOBERPROXY_BOT____DELAY", "1")]).is_err());
+        assert!(apply(&[("GOOBERPROXY_PLAYER__NAME", "someone")]).is_err());
+    }
+}
@@ -0,0 +1,68 @@
+use anyhow::{bail as yeet, Result};
+use toml::{Table, Value};
+
+/// The version of the config this build writes
+pub const CURRENT_VERSION: u32 = 2;
+
+/// Files from before the config had a version at all
+const UNVERSIONED: u32 = 1;
+
+/// Upgrades a config from each version to the next, starting at version 1
+const MIGRATIONS: &[fn(&mut Table)] = &[v1_to_v2];
+
+/// Brings a config file up to the current version, returning the version it
+/// was at if anything had to change
+pub fn migrate(table: &mut Table) -> Result<Option<u32>> {
+    let version = match table.get("version") {
+        None => UNVERSIONED,
+        Some(Value::Integer(version)) if *version >= 1 => *version as u32,
+        Some(version) => yeet!("`version` should be a positive number, not {version}"),
+    };
+
+    if version > CURRENT_VERSION {
+        yeet!(
+            "The config is for version {version}, but this build only knows up to version \
+             {CURRENT_VERSION}. Is the proxy out of date?"
+        );
+    }
+    if version == CURRENT_VERSION {
+        return Ok(None);
+    }
+
+    for migration in &MIGRATIONS[(version - 1) as usize..] {
+        migration(table);
+    }
+    table.insert(
+        "version".to_string(),
+        Value::Integer(CURRENT_VERSION as i64),
+    );
+    Ok(Some(version))
+}
+
+/// Version 1 configs are everything from before versioning. Everything added
+/// since has a default, so they only need the version.
+fn v1_to_v2(_: &mut Table) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migrates_old_configs() {
+        let mut table: Table = toml::from_str("player = \"goober\"").unwrap();
+        assert_eq!(migrate(&mut table).unwrap(), Some(UNVERSIONED));
+        assert_eq!(table["version"].as_integer(), Some(CURRENT_VERSION as i64));
+        assert_eq!(table["player"].as_str(), Some("goober"));
+
+        // Nothing left to do the second time around
+        assert_eq!(migrate(&mut table).unwrap(), None);
+    }
+
+    #[test]
+    fn rejects_unknown_versions() {
+        for version in ["0", "-1", "\"2\"", "99"] {
+            let mut table: Table = toml::from_str(&format!("version = {version}")).unwrap();
+            assert!(migrate(&mut table).is_err(), "version = {version}");
+        }
+    }
+}
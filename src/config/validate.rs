@@ -0,0 +1,206 @@
+use regex::Regex;
+use std::fmt;
+use thiserror::Error;
+
+use crate::{
+    config::{migrate::CURRENT_VERSION, AccountKind, Config, ForwardingMode},
+    events::EventKind,
+    login_queries::QueryResponders,
+};
+
+/// Everything wrong with a config that parsed fine but can't work
+#[derive(Debug, Error)]
+pub struct InvalidConfig(pub Vec<String>);
+
+impl fmt::Display for InvalidConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "The config has problems:")?;
+        for problem in &self.0 {
+            write!(f, "\n  - {problem}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Config {
+    /// Checks for settings that are the right type but make no sense
+    pub fn validate(&self) -> Result<(), InvalidConfig> {
+        let mut problems = vec![];
+        let mut check = |ok: bool, problem: &str| {
+            if !ok {
+                problems.push(problem.to_string());
+            }
+        };
+
+        check(
+            self.version == CURRENT_VERSION,
+            "`version` can't be changed by hand",
+        );
+        check(!self.player.is_empty(), "`player` is empty");
+        check(!self.account.is_empty(), "`account` is empty");
+        if self.account_kind == AccountKind::Offline {
+            check(
+                self.account.len() <= 16
+                    && self
+                        .account
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '_'),
+                "`account` has to be a valid username for offline accounts",
+            );
+        }
+        check(
+            !self.spectators.contains(&self.player),
+            "`player` is also listed under `spectators`",
+        );
+        check(
+            !self.servers.contains_key("default"),
+            "`servers` can't have one called `default`, that's `server_addr`",
+        );
+
+        check(
+            !self.proxy_protocol.enabled || !self.proxy_protocol.trusted.is_empty(),
+            "`proxy_protocol` is enabled but there are no `trusted` load balancers",
+        );
+
+        check(
+            self.forwarding.mode != ForwardingMode::Velocity || !self.forwarding.secret.is_empty(),
+            "`forwarding.mode` is velocity but there's no `forwarding.secret`",
+        );
+
+        check(
+            QueryResponders::from_config(&self.login_queries).is_ok(),
+            "`login_queries` has answers that aren't base64",
+        );
+
+        let limits = &self.limits;
+        check(
+            limits.max_connections > 0,
+            "`limits.max_connections` has to be at least 1",
+        );
+        check(
+            limits.connections_per_second.is_finite() && limits.connections_per_second > 0.0,
+            "`limits.connections_per_second` has to be above 0",
+        );
+        check(limits.burst > 0, "`limits.burst` has to be at least 1");
+
+        let timeouts = &self.timeouts;
+        check(
+            timeouts.handshake > 0
+                && timeouts.status > 0
+                && timeouts.login > 0
+                && timeouts.encryption > 0,
+            "`timeouts` have to be at least 1 second",
+        );
+
+        if let Some(min_health) = self.safety.min_health {
+            check(
+                (0.0..=20.0).contains(&min_health),
+                "`safety.min_health` has to be between 0 and 20",
+            );
+        }
+
+        check(
+            !self.chat_replay.enabled || self.chat_log.recent > 0,
+            "`chat_replay` needs `chat_log.recent` to be above 0 to remember anything",
+        );
+
+        let patterns = std::iter::once(("default_pattern", &self.auto_reply.default_pattern))
+            .chain(
+                self.auto_reply
+                    .patterns
+                    .iter()
+                    .map(|(server, pattern)| (server.as_str(), pattern)),
+            );
+        for (name, pattern) in patterns {
+            match Regex::new(pattern) {
+                Ok(regex) if regex.capture_names().flatten().any(|group| group == "name") => {}
+                Ok(_) => check(
+                    false,
+                    &format!("The auto reply pattern for {name} has no group called `name`"),
+                ),
+                Err(err) => check(
+                    false,
+                    &format!("The auto reply pattern for {name} is invalid: {err}"),
+                ),
+            }
+        }
+
+        match Regex::new(&self.notifications.queue_pattern) {
+            Ok(regex)
+                if regex
+                    .capture_names()
+                    .flatten()
+                    .any(|group| group == "position") => {}
+            Ok(_) => check(
+                false,
+                "`notifications.queue_pattern` has no group called `position`",
+            ),
+            Err(err) => check(
+                false,
+                &format!("`notifications.queue_pattern` is invalid: {err}"),
+            ),
+        }
+
+        check(
+            !self.api.enabled || !self.api.tokens.is_empty(),
+            "`api` is enabled but has no `tokens`, so nobody could use it",
+        );
+        check(
+            self.api.tokens.iter().all(|token| !token.is_empty()),
+            "`api.tokens` has an empty token",
+        );
+
+        for sink in &self.notifications.sinks {
+            for event in &sink.events {
+                check(
+                    EventKind::NAMES.contains(&event.as_str()),
+                    &format!(
+                        "`{event}` isn't a type of event, it can be one of {}",
+                        EventKind::NAMES.join(", ")
+                    ),
+                );
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(InvalidConfig(problems))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_the_default_config() {
+        assert!(Config::default().validate().is_ok());
+    }
+
+    #[test]
+    fn collects_every_problem() {
+        let mut config = Config {
+            account: "not an offline name".to_string(),
+            account_kind: AccountKind::Offline,
+            spectators: vec!["LiveOvergoober".to_string()],
+            ..Config::default()
+        };
+        config.limits.burst = 0;
+        config.auto_reply.default_pattern = r"^(\w+) whispers: ".to_string();
+        config.api.enabled = true;
+
+        let InvalidConfig(problems) = config.validate().unwrap_err();
+        assert_eq!(
+            problems,
+            [
+                "`account` has to be a valid username for offline accounts",
+                "`player` is also listed under `spectators`",
+                "`limits.burst` has to be at least 1",
+                "The auto reply pattern for default_pattern has no group called `name`",
+                "`api` is enabled but has no `tokens`, so nobody could use it",
+            ]
+        );
+    }
+}
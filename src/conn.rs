@@ -1,15 +1,38 @@
 #![allow(dead_code)]
 
+use aes::{
+    cipher::{inout::InOutBuf, BlockDecryptMut, BlockEncryptMut, KeyIvInit},
+    Aes128,
+};
 use azalea_protocol::{
-    connect::Connection,
     packets::{
         game::{ClientboundGamePacket, ServerboundGamePacket},
         handshake::{ClientboundHandshakePacket, ServerboundHandshakePacket},
         login::{ClientboundLoginPacket, ServerboundLoginPacket},
         status::{ClientboundStatusPacket, ServerboundStatusPacket},
+        ProtocolPacket,
+    },
+    read::{deserialize_packet, ReadPacketError},
+    write::packet_encoder,
+};
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+use std::{
+    fmt::{self, Debug},
+    io::{self, Cursor, Read, Write},
+    marker::PhantomData,
+    net::SocketAddr,
+};
+use thiserror::Error;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+        TcpStream,
     },
 };
 
+use crate::capture::{Capture, Direction, State};
+
 pub type ServerHandshakeConn = Connection<ServerboundHandshakePacket, ClientboundHandshakePacket>;
 pub type ServerStatusConn = Connection<ServerboundStatusPacket, ClientboundStatusPacket>;
 pub type ServerLoginConn = Connection<ServerboundLoginPacket, ClientboundLoginPacket>;
@@ -19,3 +42,431 @@ pub type ClientHandshakeConn = Connection<ClientboundHandshakePacket, Serverboun
 pub type ClientStatusConn = Connection<ClientboundStatusPacket, ServerboundStatusPacket>;
 pub type ClientLoginConn = Connection<ClientboundLoginPacket, ServerboundLoginPacket>;
 pub type ClientGameConn = Connection<ClientboundGamePacket, ServerboundGamePacket>;
+
+/// The longest frame the length prefix can describe, same as vanilla
+const MAX_FRAME_LENGTH: usize = 2097151;
+
+/// The most a compressed packet may say it inflates to, same as vanilla
+const MAX_PACKET_LENGTH: usize = 8388608;
+
+type Encryptor = cfb8::Encryptor<Aes128>;
+type Decryptor = cfb8::Decryptor<Aes128>;
+
+/// The packets sent one way in one state, which is all a connection needs to
+/// know to capture them
+pub trait Packets: ProtocolPacket + Debug {
+    const STATE: State;
+    const CLIENTBOUND: bool;
+}
+
+macro_rules! packets {
+    ($($packets:ty => $state:ident, $clientbound:literal;)*) => {
+        $(impl Packets for $packets {
+            const STATE: State = State::$state;
+            const CLIENTBOUND: bool = $clientbound;
+        })*
+    };
+}
+
+packets! {
+    ServerboundHandshakePacket => Handshake, false;
+    ClientboundHandshakePacket => Handshake, true;
+    ServerboundStatusPacket => Status, false;
+    ClientboundStatusPacket => Status, true;
+    ServerboundLoginPacket => Login, false;
+    ClientboundLoginPacket => Login, true;
+    ServerboundGamePacket => Game, false;
+    ClientboundGamePacket => Game, true;
+}
+
+#[derive(Error, Debug)]
+pub enum ReadError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+
+    #[error(transparent)]
+    Packet(#[from] Box<ReadPacketError>),
+}
+
+/// A connection that reads `R` and writes `W`, doing the framing, compression
+/// and encryption itself so every frame can be captured before it's decoded
+pub struct Connection<R, W> {
+    pub reader: ReadConnection<R>,
+    pub writer: WriteConnection<W>,
+}
+
+pub struct ReadConnection<R> {
+    stream: OwnedReadHalf,
+
+    /// What was read but isn't a whole frame yet, already decrypted
+    buffer: Vec<u8>,
+    compression_threshold: Option<usize>,
+    cipher: Option<Decryptor>,
+    capture: Option<Capture>,
+    _packets: PhantomData<R>,
+}
+
+pub struct WriteConnection<W> {
+    stream: OwnedWriteHalf,
+    compression_threshold: Option<usize>,
+    cipher: Option<Encryptor>,
+    capture: Option<Capture>,
+    _packets: PhantomData<W>,
+}
+
+impl<R> Debug for ReadConnection<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ReadConnection")
+            .field("stream", &self.stream)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<W> Debug for WriteConnection<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("WriteConnection")
+            .field("stream", &self.stream)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<R, W> Connection<R, W> {
+    pub fn wrap(stream: TcpStream) -> Self {
+        let (read, write) = stream.into_split();
+        Self {
+            reader: ReadConnection {
+                stream: read,
+                buffer: vec![],
+                compression_threshold: None,
+                cipher: None,
+                capture: None,
+                _packets: PhantomData,
+            },
+            writer: WriteConnection {
+                stream: write,
+                compression_threshold: None,
+                cipher: None,
+                capture: None,
+                _packets: PhantomData,
+            },
+        }
+    }
+
+    pub async fn new(addr: &SocketAddr) -> io::Result<Self> {
+        let stream = TcpStream::connect(addr).await?;
+        stream.set_nodelay(true)?;
+        Ok(Self::wrap(stream))
+    }
+
+    /// Captures every frame read or written from now on
+    pub fn captured(mut self, capture: Option<&Capture>) -> Self {
+        self.reader.capture = capture.cloned();
+        self.writer.capture = capture.cloned();
+        self
+    }
+
+    /// Moves the connection to another state, keeping everything else
+    pub fn from<R2, W2>(conn: Self) -> Connection<R2, W2> {
+        let Self { reader, writer } = conn;
+        Connection {
+            reader: ReadConnection {
+                stream: reader.stream,
+                buffer: reader.buffer,
+                compression_threshold: reader.compression_threshold,
+                cipher: reader.cipher,
+                capture: reader.capture,
+                _packets: PhantomData,
+            },
+            writer: WriteConnection {
+                stream: writer.stream,
+                compression_threshold: writer.compression_threshold,
+                cipher: writer.cipher,
+                capture: writer.capture,
+                _packets: PhantomData,
+            },
+        }
+    }
+
+    /// Compresses packets of at least `threshold` bytes, or none if it's
+    /// negative
+    pub fn set_compression_threshold(&mut self, threshold: i32) {
+        let threshold = usize::try_from(threshold).ok();
+        self.reader.compression_threshold = threshold;
+        self.writer.compression_threshold = threshold;
+    }
+
+    /// Encrypts everything after this with the shared secret
+    pub fn set_encryption_key(&mut self, key: [u8; 16]) {
+        let mut decryptor = Decryptor::new(&key.into(), &key.into());
+        // Whatever came in after the key was already encrypted
+        decrypt(&mut decryptor, &mut self.reader.buffer);
+        self.reader.cipher = Some(decryptor);
+        self.writer.cipher = Some(Encryptor::new(&key.into(), &key.into()));
+    }
+
+    pub fn into_split(self) -> (ReadConnection<R>, WriteConnection<W>) {
+        (self.reader, self.writer)
+    }
+}
+
+impl<R: Packets, W: Packets> Connection<R, W> {
+    pub async fn read(&mut self) -> Result<R, ReadError> {
+        self.reader.read().await
+    }
+
+    pub async fn write(&mut self, packet: W) -> io::Result<()> {
+        self.writer.write(packet).await
+    }
+}
+
+impl ClientHandshakeConn {
+    pub fn login(self) -> ClientLoginConn {
+        Connection::from(self)
+    }
+
+    pub fn status(self) -> ClientStatusConn {
+        Connection::from(self)
+    }
+}
+
+impl ClientLoginConn {
+    pub fn game(self) -> ClientGameConn {
+        Connection::from(self)
+    }
+}
+
+impl ServerLoginConn {
+    pub fn game(self) -> ServerGameConn {
+        Connection::from(self)
+    }
+}
+
+impl<R: Packets> ReadConnection<R> {
+    /// Reads the next packet, capturing its frame even if it can't be decoded
+    pub async fn read(&mut self) -> Result<R, ReadError> {
+        let data = self.read_frame().await?;
+        if let Some(capture) = &self.capture {
+            let direction = if R::CLIENTBOUND {
+                Direction::FromServer
+            } else {
+                Direction::FromClient
+            };
+            capture.record(direction, R::STATE, &data);
+        }
+        Ok(deserialize_packet(&mut Cursor::new(&data[..]))?)
+    }
+
+    /// Reads the packet id and data of the next frame
+    async fn read_frame(&mut self) -> io::Result<Vec<u8>> {
+        loop {
+            if let Some((length, frame)) = split_frame(&self.buffer)? {
+                let data = decompress(frame, self.compression_threshold)?;
+                self.buffer.drain(..length);
+                return Ok(data);
+            }
+
+            let start = self.buffer.len();
+            if self.stream.read_buf(&mut self.buffer).await? == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            if let Some(cipher) = &mut self.cipher {
+                decrypt(cipher, &mut self.buffer[start..]);
+            }
+        }
+    }
+}
+
+impl<W: Packets> WriteConnection<W> {
+    pub async fn write(&mut self, packet: W) -> io::Result<()> {
+        let data = packet_encoder(&packet)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))?;
+        if let Some(capture) = &self.capture {
+            let direction = if W::CLIENTBOUND {
+                Direction::ToClient
+            } else {
+                Direction::ToServer
+            };
+            capture.record(direction, W::STATE, &data);
+        }
+
+        let mut frame = frame(&data, self.compression_threshold)?;
+        if let Some(cipher) = &mut self.cipher {
+            encrypt(cipher, &mut frame);
+        }
+        self.stream.write_all(&frame).await
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Reads a varint, returning it and how many bytes it took, or `None` if the
+/// bytes end before it does
+fn read_varint(bytes: &[u8]) -> io::Result<Option<(u32, usize)>> {
+    let mut value = 0;
+    for (i, byte) in bytes.iter().enumerate() {
+        if i == 5 {
+            break;
+        }
+        value |= ((byte & 0x7f) as u32) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok(Some((value, i + 1)));
+        }
+    }
+    if bytes.len() < 5 {
+        Ok(None)
+    } else {
+        Err(invalid("varint is too long"))
+    }
+}
+
+pub fn write_varint(buf: &mut Vec<u8>, mut value: u32) {
+    loop {
+        if value & !0x7f == 0 {
+            buf.push(value as u8);
+            return;
+        }
+        buf.push((value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+}
+
+/// Finds the first frame in `buffer`, returning how many bytes it takes up
+/// with its length and the frame itself, or `None` if it isn't all there yet
+fn split_frame(buffer: &[u8]) -> io::Result<Option<(usize, &[u8])>> {
+    let Some((length, prefix)) = read_varint(buffer)? else {
+        return Ok(None);
+    };
+    let length = length as usize;
+    if length > MAX_FRAME_LENGTH {
+        return Err(invalid("frame is too long"));
+    }
+    Ok(buffer
+        .get(prefix..prefix + length)
+        .map(|frame| (prefix + length, frame)))
+}
+
+/// Turns a frame back into the packet id and data
+fn decompress(frame: &[u8], threshold: Option<usize>) -> io::Result<Vec<u8>> {
+    if threshold.is_none() {
+        return Ok(frame.to_vec());
+    }
+
+    // Packets under the threshold say they inflate to nothing
+    let (length, prefix) = read_varint(frame)?.ok_or_else(|| invalid("frame is empty"))?;
+    let length = length as usize;
+    if length == 0 {
+        return Ok(frame[prefix..].to_vec());
+    }
+    if length > MAX_PACKET_LENGTH {
+        return Err(invalid("packet is too long"));
+    }
+
+    let mut data = Vec::with_capacity(length);
+    ZlibDecoder::new(&frame[prefix..])
+        .take(length as u64 + 1)
+        .read_to_end(&mut data)?;
+    if data.len() != length {
+        return Err(invalid("packet isn't as long as it says"));
+    }
+    Ok(data)
+}
+
+/// Puts the packet id and data into a frame, compressed if it's big enough
+fn frame(data: &[u8], threshold: Option<usize>) -> io::Result<Vec<u8>> {
+    let body = match threshold {
+        None => data.to_vec(),
+        Some(threshold) if data.len() < threshold => {
+            let mut body = vec![0];
+            body.extend(data);
+            body
+        }
+        Some(_) => {
+            let mut body = vec![];
+            write_varint(&mut body, data.len() as u32);
+            let mut encoder = ZlibEncoder::new(body, Compression::default());
+            encoder.write_all(data)?;
+            encoder.finish()?
+        }
+    };
+    if body.len() > MAX_FRAME_LENGTH {
+        return Err(invalid("packet is too long"));
+    }
+
+    let mut frame = Vec::with_capacity(body.len() + 3);
+    write_varint(&mut frame, body.len() as u32);
+    frame.extend(body);
+    Ok(frame)
+}
+
+fn encrypt(cipher: &mut Encryptor, data: &mut [u8]) {
+    let (blocks, _) = InOutBuf::from(data).into_chunks();
+    cipher.encrypt_blocks_inout_mut(blocks);
+}
+
+fn decrypt(cipher: &mut Decryptor, data: &mut [u8]) {
+    let (blocks, _) = InOutBuf::from(data).into_chunks();
+    cipher.decrypt_blocks_inout_mut(blocks);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_the_varints_it_writes() {
+        let mut buf = vec![];
+        write_varint(&mut buf, 1);
+        write_varint(&mut buf, 300);
+        assert_eq!(buf, [0x01, 0xac, 0x02]);
+
+        assert_eq!(read_varint(&buf[1..]).unwrap(), Some((300, 2)));
+        assert_eq!(read_varint(&buf[1..2]).unwrap(), None);
+    }
+
+    #[test]
+    fn splits_frames_as_they_come_in() {
+        let mut buffer = frame(&[1, 2, 3], None).unwrap();
+        buffer.extend(frame(&[4], None).unwrap());
+
+        assert_eq!(split_frame(&buffer[..2]).unwrap(), None);
+        assert_eq!(split_frame(&buffer).unwrap(), Some((4, &[1, 2, 3][..])));
+        assert_eq!(split_frame(&buffer[4..]).unwrap(), Some((2, &[4][..])));
+        assert!(split_frame(&[0xff, 0xff, 0xff, 0xff, 0xff]).is_err());
+    }
+
+    #[test]
+    fn compresses_only_big_packets() {
+        let small = [7; 10];
+        let big = [7; 1000];
+
+        let frame_small = frame(&small, Some(256)).unwrap();
+        assert_eq!(frame_small[1], 0);
+        let frame_big = frame(&big, Some(256)).unwrap();
+        assert!(frame_big.len() < big.len());
+
+        for (data, framed) in [(&small[..], frame_small), (&big[..], frame_big)] {
+            let (_, body) = split_frame(&framed).unwrap().unwrap();
+            assert_eq!(decompress(body, Some(256)).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn decrypts_what_it_encrypts() {
+        let key = [3; 16];
+        let mut encryptor = Encryptor::new(&key.into(), &key.into());
+        let mut decryptor = Decryptor::new(&key.into(), &key.into());
+
+        let mut data = frame(&[1, 2, 3, 4, 5], None).unwrap();
+        let original = data.clone();
+        encrypt(&mut encryptor, &mut data);
+        assert_ne!(data, original);
+
+        // The stream can be decrypted in whatever pieces it arrives in
+        let (first, second) = data.split_at_mut(2);
+        decrypt(&mut decryptor, first);
+        decrypt(&mut decryptor, second);
+        assert_eq!(data, original);
+    }
+}
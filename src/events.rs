@@ -0,0 +1,157 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::fmt;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use crate::chat_log::ChatMessage;
+
+/// How many events a slow subscriber can fall behind before it starts missing
+/// some
+const CHANNEL_CAPACITY: usize = 256;
+
+/// Something that happened in the proxy that someone might want to know about
+#[derive(Debug, Clone, Serialize)]
+pub struct ProxyEvent {
+    pub time: DateTime<Utc>,
+
+    #[serde(flatten)]
+    pub kind: EventKind,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EventKind {
+    /// Another player appeared in the bot's render distance
+    PlayerEnteredRange(PlayerSighting),
+
+    /// Another player disappeared from the bot's render distance
+    PlayerLeftRange(PlayerSighting),
+
+    /// The bot joined a server
+    BotConnected { server: String },
+
+    /// The bot lost its connection or was logged out
+    BotDisconnected { reason: String },
+
+    /// The bot moved up to one of the queue milestones in the config
+    QueuePosition { position: u32 },
+
+    /// The bot died
+    Died { message: String },
+
+    /// Someone whispered to the bot while nobody was controlling it
+    Whisper { from: String, message: String },
+
+    /// Someone said something in chat
+    Chat { message: ChatMessage },
+
+    /// Logging into the account needs someone to authenticate in the browser,
+    /// see the logs for the code
+    AuthPrompt { account: String },
+}
+
+impl EventKind {
+    /// The name of every type of event, as used in configs
+    pub const NAMES: &'static [&'static str] = &[
+        "player_entered_range",
+        "player_left_range",
+        "bot_connected",
+        "bot_disconnected",
+        "queue_position",
+        "died",
+        "whisper",
+        "chat",
+        "auth_prompt",
+    ];
+
+    /// The name of this type of event, the same as its `type` in JSON
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::PlayerEnteredRange(_) => "player_entered_range",
+            Self::PlayerLeftRange(_) => "player_left_range",
+            Self::BotConnected { .. } => "bot_connected",
+            Self::BotDisconnected { .. } => "bot_disconnected",
+            Self::QueuePosition { .. } => "queue_position",
+            Self::Died { .. } => "died",
+            Self::Whisper { .. } => "whisper",
+            Self::Chat { .. } => "chat",
+            Self::AuthPrompt { .. } => "auth_prompt",
+        }
+    }
+
+    /// Whether sinks only get this type of event if they ask for it by name,
+    /// since there are so many of them
+    pub fn is_opt_in(&self) -> bool {
+        matches!(self, Self::Chat { .. })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PlayerSighting {
+    pub name: String,
+    pub uuid: Uuid,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl fmt::Display for EventKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::PlayerEnteredRange(player) => write!(f, "{player} entered visual range"),
+            Self::PlayerLeftRange(player) => write!(f, "{player} left visual range"),
+            Self::BotConnected { server } => write!(f, "The bot joined {server}"),
+            Self::BotDisconnected { reason } => write!(f, "The bot disconnected: {reason}"),
+            Self::QueuePosition { position } => write!(f, "The bot is at {position} in the queue"),
+            Self::Died { message } => write!(f, "The bot died: {message}"),
+            Self::Whisper { from, message } => write!(f, "{from} whispered: {message}"),
+            Self::Chat { message } => write!(f, "{}", message.message),
+            Self::AuthPrompt { account } => {
+                write!(f, "{account} needs to be authenticated, check the logs")
+            }
+        }
+    }
+}
+
+impl fmt::Display for PlayerSighting {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({}) at {:.0}, {:.0}, {:.0}",
+            self.name, self.uuid, self.x, self.y, self.z
+        )
+    }
+}
+
+/// Broadcasts events to everything that wants to hear about them
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<ProxyEvent>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Self { sender }
+    }
+
+    /// Sends an event to every subscriber. It's fine if there are none.
+    pub fn emit(&self, kind: EventKind) {
+        let event = ProxyEvent {
+            time: Utc::now(),
+            kind,
+        };
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<ProxyEvent> {
+        self.sender.subscribe()
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
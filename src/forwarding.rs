@@ -0,0 +1,226 @@
+use anyhow::Result;
+use azalea_client::Account;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::net::IpAddr;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::{
+    app::App,
+    config::ForwardingMode,
+    conn::write_varint,
+    login_queries::{CustomQueryHandler, QueryAnswer},
+};
+
+/// The channel Velocity asks for the player's info on
+const VELOCITY_CHANNEL: &str = "velocity:player_info";
+
+/// The version of Velocity's forwarding we speak, the one without the chat
+/// signing key
+const VELOCITY_VERSION: u8 = 1;
+
+/// Who is joining, as told to a server in offline mode behind the proxy
+#[derive(Debug, Clone)]
+pub struct PlayerInfo {
+    pub ip: IpAddr,
+    pub uuid: Uuid,
+    pub name: String,
+    pub properties: Vec<ProfileProperty>,
+}
+
+/// A property of the player's profile, like their skin
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProfileProperty {
+    pub name: String,
+    pub value: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct SessionProfile {
+    #[serde(default)]
+    properties: Vec<ProfileProperty>,
+}
+
+impl App {
+    /// Who the bot is, if the config says to forward it to the server
+    pub async fn forwarded_player(&self, account: &Account) -> Option<PlayerInfo> {
+        let config = self.config.get();
+        if config.forwarding.mode == ForwardingMode::None {
+            return None;
+        }
+
+        // Offline accounts don't have a skin to fetch
+        let (uuid, properties) = match account.uuid {
+            Some(uuid) => match fetch_properties(&uuid).await {
+                Ok(properties) => (uuid, properties),
+                Err(err) => {
+                    warn!("Failed to fetch the bot's skin, forwarding it without one: {err}");
+                    (uuid, vec![])
+                }
+            },
+            None => (
+                azalea_auth::offline::generate_uuid(&account.username),
+                vec![],
+            ),
+        };
+
+        Some(PlayerInfo {
+            ip: config.forwarding.ip,
+            uuid,
+            name: account.username.clone(),
+            properties,
+        })
+    }
+}
+
+/// Asks Mojang for the player's profile properties, signed so the server
+/// trusts their skin
+pub async fn fetch_properties(uuid: &Uuid) -> Result<Vec<ProfileProperty>> {
+    let url = format!(
+        "https://sessionserver.mojang.com/session/minecraft/profile/{}?unsigned=false",
+        uuid.simple()
+    );
+    let profile: SessionProfile = reqwest::get(url).await?.error_for_status()?.json().await?;
+    Ok(profile.properties)
+}
+
+/// The handshake's host with the player tacked on like BungeeCord's legacy
+/// forwarding does it
+pub fn bungeecord_hostname(host: &str, player: &PlayerInfo) -> String {
+    let properties = serde_json::to_string(&player.properties).unwrap_or_default();
+    format!(
+        "{host}\0{}\0{}\0{properties}",
+        player.ip,
+        player.uuid.simple()
+    )
+}
+
+/// Answers Velocity's modern forwarding request with the player's info,
+/// signed with the secret the server shares with us. Everything else goes to
+/// `fallback`.
+pub struct VelocityForwarding<'a> {
+    pub secret: &'a str,
+    pub player: &'a PlayerInfo,
+    pub fallback: &'a dyn CustomQueryHandler,
+}
+
+impl CustomQueryHandler for VelocityForwarding<'_> {
+    fn answer<'a>(&'a self, channel: &'a str, data: &'a [u8]) -> QueryAnswer<'a> {
+        if channel != VELOCITY_CHANNEL {
+            return self.fallback.answer(channel, data);
+        }
+
+        // The server tells us the newest version it knows, if anything
+        let answer = match data.first() {
+            Some(&version) if version < VELOCITY_VERSION => None,
+            _ => Some(self.signed_info()),
+        };
+        Box::pin(async move { answer })
+    }
+}
+
+impl VelocityForwarding<'_> {
+    fn signed_info(&self) -> Vec<u8> {
+        let mut info = vec![];
+        write_varint(&mut info, VELOCITY_VERSION as u32);
+        write_string(&mut info, &self.player.ip.to_string());
+        info.extend(self.player.uuid.as_u128().to_be_bytes());
+        write_string(&mut info, &self.player.name);
+        write_varint(&mut info, self.player.properties.len() as u32);
+        for property in &self.player.properties {
+            write_string(&mut info, &property.name);
+            write_string(&mut info, &property.value);
+            info.push(property.signature.is_some() as u8);
+            if let Some(signature) = &property.signature {
+                write_string(&mut info, signature);
+            }
+        }
+
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.secret.as_bytes())
+            .expect("HMAC takes keys of any length");
+        mac.update(&info);
+
+        let mut answer = mac.finalize().into_bytes().to_vec();
+        answer.extend(info);
+        answer
+    }
+}
+
+fn write_string(buf: &mut Vec<u8>, value: &str) {
+    write_varint(buf, value.len() as u32);
+    buf.extend(value.as_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::login_queries::QueryResponders;
+
+    use super::*;
+
+    fn player() -> PlayerInfo {
+        PlayerInfo {
+            ip: IpAddr::from([203, 0, 113, 5]),
+            uuid: "b50ad385-829d-3141-a216-7e7d7539ba7f".parse().unwrap(),
+            name: "Notch".to_string(),
+            properties: vec![ProfileProperty {
+                name: "textures".to_string(),
+                value: "e30=".to_string(),
+                signature: Some("c2ln".to_string()),
+            }],
+        }
+    }
+
+    #[test]
+    fn encodes_bungeecord_hostnames() {
+        let parts = [
+            "mc.example.com",
+            "203.0.113.5",
+            "b50ad385829d3141a2167e7d7539ba7f",
+            r#"[{"name":"textures","value":"e30=","signature":"c2ln"}]"#,
+        ];
+        assert_eq!(
+            bungeecord_hostname("mc.example.com", &player()),
+            parts.join("\0")
+        );
+    }
+
+    #[test]
+    fn derives_offline_uuids_like_servers_do() {
+        // Servers in offline mode make up the same UUID from the name, so the
+        // forwarded one has to match it
+        assert_eq!(azalea_auth::offline::generate_uuid("Notch"), player().uuid);
+    }
+
+    #[tokio::test]
+    async fn signs_velocity_info() {
+        let player = player();
+        let fallback = QueryResponders::default().on("fml:handshake", |_| Some(vec![1]));
+        let forwarding = VelocityForwarding {
+            secret: "hunter2",
+            player: &player,
+            fallback: &fallback,
+        };
+
+        let answer = forwarding.answer(VELOCITY_CHANNEL, &[1]).await.unwrap();
+        let (signature, info) = answer.split_at(32);
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"hunter2").unwrap();
+        mac.update(info);
+        mac.verify_slice(signature).unwrap();
+
+        let mut expected = vec![VELOCITY_VERSION, 11];
+        expected.extend(b"203.0.113.5");
+        expected.extend(player.uuid.as_bytes());
+        expected.extend(b"\x05Notch\x01\x08textures\x04e30=\x01\x04c2ln");
+        assert_eq!(info, expected);
+
+        // Older Velocity doesn't say which version it wants
+        assert!(forwarding.answer(VELOCITY_CHANNEL, &[]).await.is_some());
+        assert_eq!(forwarding.answer(VELOCITY_CHANNEL, &[0]).await, None);
+        assert_eq!(forwarding.answer("fml:handshake", &[]).await, Some(vec![1]));
+    }
+}
@@ -0,0 +1,112 @@
+use anyhow::{Context, Result};
+use azalea_protocol::{
+    packets::{
+        game::{ClientboundGamePacket, ServerboundGamePacket},
+        handshake::{ClientboundHandshakePacket, ServerboundHandshakePacket},
+        login::{ClientboundLoginPacket, ServerboundLoginPacket},
+        status::{ClientboundStatusPacket, ServerboundStatusPacket},
+        ProtocolPacket,
+    },
+    read::deserialize_packet,
+};
+use clap::Args;
+use std::{fmt::Debug, io::Cursor, path::PathBuf};
+
+use crate::capture::{read_capture, CapturedPacket, Direction, State};
+
+/// Prints the packets in a capture file
+#[derive(Debug, Args)]
+pub struct InspectArgs {
+    /// The capture file to read
+    file: PathBuf,
+
+    /// Only show packets going this way
+    #[arg(long)]
+    direction: Option<Direction>,
+
+    /// Only show packets sent in this state
+    #[arg(long)]
+    state: Option<State>,
+
+    /// Only show packets that contain this text once decoded
+    #[arg(long)]
+    filter: Option<String>,
+
+    /// Also print the raw bytes of every packet
+    #[arg(long)]
+    raw: bool,
+}
+
+pub async fn inspect(args: InspectArgs) -> Result<()> {
+    let bytes = tokio::fs::read(&args.file)
+        .await
+        .context("Failed to read the capture")?;
+
+    for packet in read_capture(&bytes)? {
+        if args
+            .direction
+            .map_or(false, |direction| direction != packet.direction)
+            || args.state.map_or(false, |state| state != packet.state)
+        {
+            continue;
+        }
+
+        let decoded = decode(&packet).unwrap_or_else(|err| format!("Failed to decode: {err}"));
+        if let Some(filter) = &args.filter {
+            if !decoded.contains(filter.as_str()) {
+                continue;
+            }
+        }
+
+        let id = packet_id(&packet.data).map_or("?".to_string(), |id| format!("0x{id:02x}"));
+        println!(
+            "[{:>10.3}s] {:<15} {:<9} {id:>4} {decoded}",
+            packet.time as f64 / 1000.0,
+            packet.direction,
+            packet.state
+        );
+        if args.raw {
+            let hex = packet
+                .data
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect::<Vec<_>>()
+                .join(" ");
+            println!("    {hex}");
+        }
+    }
+
+    Ok(())
+}
+
+/// Decodes a captured packet into its debug representation
+fn decode(packet: &CapturedPacket) -> Result<String> {
+    let data = packet.data.as_slice();
+    match (packet.state, packet.direction.is_clientbound()) {
+        (State::Handshake, true) => debug::<ClientboundHandshakePacket>(data),
+        (State::Handshake, false) => debug::<ServerboundHandshakePacket>(data),
+        (State::Status, true) => debug::<ClientboundStatusPacket>(data),
+        (State::Status, false) => debug::<ServerboundStatusPacket>(data),
+        (State::Login, true) => debug::<ClientboundLoginPacket>(data),
+        (State::Login, false) => debug::<ServerboundLoginPacket>(data),
+        (State::Game, true) => debug::<ClientboundGamePacket>(data),
+        (State::Game, false) => debug::<ServerboundGamePacket>(data),
+    }
+}
+
+fn debug<P: ProtocolPacket + Debug>(data: &[u8]) -> Result<String> {
+    let packet: P = deserialize_packet(&mut Cursor::new(data))?;
+    Ok(format!("{packet:?}"))
+}
+
+/// Reads the packet id, which is the varint the data starts with
+fn packet_id(data: &[u8]) -> Option<u32> {
+    let mut id = 0;
+    for (i, byte) in data.iter().take(5).enumerate() {
+        id |= ((byte & 0x7f) as u32) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some(id);
+        }
+    }
+    None
+}
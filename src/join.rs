@@ -1,191 +1,188 @@
-use azalea_auth::{game_profile::GameProfile, sessionserver::ClientSessionServerError};
+use azalea_auth::{
+    game_profile::GameProfile,
+    sessionserver::{self, ClientSessionServerError},
+};
 use azalea_chat::FormattedText;
 use azalea_client::Account;
-use azalea_protocol::{
-    connect::{Connection, ConnectionError},
-    packets::{
-        handshake::client_intention_packet::ClientIntentionPacket,
-        login::{
-            serverbound_hello_packet::ServerboundHelloPacket,
-            serverbound_key_packet::{NonceOrSaltSignature, ServerboundKeyPacket},
-            ClientboundLoginPacket,
-        },
-        ConnectionProtocol, PROTOCOL_VERSION,
+use azalea_protocol::packets::{
+    handshake::client_intention_packet::ClientIntentionPacket,
+    login::{
+        clientbound_custom_query_packet::ClientboundCustomQueryPacket,
+        clientbound_hello_packet::ClientboundHelloPacket,
+        serverbound_custom_query_packet::ServerboundCustomQueryPacket,
+        serverbound_hello_packet::ServerboundHelloPacket,
+        serverbound_key_packet::{NonceOrSaltSignature, ServerboundKeyPacket},
+        ClientboundLoginPacket,
     },
-    read::ReadPacketError,
+    ConnectionProtocol, PROTOCOL_VERSION,
 };
 use std::net::SocketAddr;
 use thiserror::Error;
-
-use crate::conn::{ClientGameConn, ClientLoginConn};
+use tracing::debug;
+
+use crate::{
+    capture::Capture,
+    conn::{ClientGameConn, ClientHandshakeConn, ClientLoginConn, Connection, ReadError},
+    forwarding::{self, PlayerInfo},
+    login_queries::CustomQueryHandler,
+    outbound::OutboundProxy,
+};
 
 #[derive(Error, Debug)]
 pub enum JoinServerError {
     #[error("disconnected: {0}")]
     Disconnected(FormattedText),
 
-    #[error("invalid account: account has no access token or UUID")]
+    #[error("the server is in online mode, but the account isn't logged into Microsoft")]
     InvalidAccount,
 
-    #[error("unexpected packet: {0:?}")]
-    UnexpectedPacket(ClientboundLoginPacket),
-
-    #[error(transparent)]
-    Connection(#[from] ConnectionError),
-
     #[error(transparent)]
     Io(#[from] std::io::Error),
 
     #[error(transparent)]
-    ReadPacket(#[from] Box<ReadPacketError>),
+    Read(#[from] ReadError),
 
     #[error(transparent)]
     SessionServer(#[from] ClientSessionServerError),
 }
 
-/// Start a connection, authenticate and join the server without sending the
-/// encryption response
+/// Start a connection, authenticate if the server is in online mode and join
+/// the server
 ///
-/// This function assumes that `account` has an access token and UUID
-pub async fn almost_join_server(
+/// With `bungeecord` set, the player is forwarded in the handshake like
+/// BungeeCord does, for servers in offline mode behind it.
+pub async fn join_server(
     addr: &SocketAddr,
     account: &Account,
-) -> Result<(ClientLoginConn, ServerboundKeyPacket, [u8; 16]), JoinServerError> {
-    // I warned you
-    if account.access_token.is_none() || account.uuid.is_none() {
-        return Err(JoinServerError::InvalidAccount);
-    }
-
+    proxy: Option<&OutboundProxy>,
+    capture: Option<&Capture>,
+    queries: &dyn CustomQueryHandler,
+    bungeecord: Option<&PlayerInfo>,
+) -> Result<(ClientGameConn, GameProfile), JoinServerError> {
     // Initialize the connection (real)
-    let mut conn = Connection::new(addr).await?;
+    let conn: ClientHandshakeConn = match proxy {
+        Some(proxy) => Connection::wrap(proxy.connect(addr).await?),
+        None => Connection::new(addr).await?,
+    };
+    let mut conn = conn.captured(capture);
 
     // Handshake
-    conn.write(
-        ClientIntentionPacket {
-            protocol_version: PROTOCOL_VERSION,
-            hostname: addr.ip().to_string(),
-            port: addr.port(),
-            intention: ConnectionProtocol::Login,
-        }
-        .get(),
-    )
-    .await?;
+    let host = addr.ip().to_string();
+    let handshake = ClientIntentionPacket {
+        protocol_version: PROTOCOL_VERSION,
+        hostname: match bungeecord {
+            Some(player) => forwarding::bungeecord_hostname(&host, player),
+            None => host,
+        },
+        port: addr.port(),
+        intention: ConnectionProtocol::Login,
+    }
+    .get();
+    conn.write(handshake).await?;
 
     // Hello!
     let mut conn = conn.login();
-    conn.write(
-        ServerboundHelloPacket {
-            username: account.username.clone(),
-            public_key: None,
-            profile_id: None,
-        }
-        .get(),
-    )
-    .await?;
-
-    // Hello?
-    let encryption_request = match conn.read().await? {
-        ClientboundLoginPacket::Hello(packet) => packet,
-        ClientboundLoginPacket::LoginDisconnect(packet) => {
-            return Err(JoinServerError::Disconnected(packet.reason));
-        }
-        packet => {
-            return Err(JoinServerError::UnexpectedPacket(packet));
-        }
-    };
-
-    // Lettuce do le auth (auth so goofy)
-    // ↑ this file has been copied so much from project to project
-    // that I don't even know who wrote this anymore
-    let encryption_result =
-        azalea_crypto::encrypt(&encryption_request.public_key, &encryption_request.nonce).unwrap();
-
-    // Very secur (I hope)
-    let secret_key = encryption_result.secret_key.to_owned();
-
-    // Here we actually do the auth smh
-    let access_token = { account.access_token.as_ref().unwrap().lock().to_owned() };
-    conn.authenticate(
-        &access_token,
-        &account.uuid.unwrap(),
-        secret_key,
-        &encryption_request,
-    )
-    .await?;
-
-    Ok((
-        conn,
-        ServerboundKeyPacket {
-            key_bytes: encryption_result.encrypted_public_key,
-            nonce_or_salt_signature: NonceOrSaltSignature::Nonce(encryption_result.encrypted_nonce),
-        },
-        secret_key,
-    ))
-}
-
-/// Finish joining the server by sending the encryption response and waiting for
-/// the game profile
-pub async fn finish_joining_server(
-    mut conn: ClientLoginConn,
-    packet: ServerboundKeyPacket,
-    sk: [u8; 16],
-) -> Result<(ClientGameConn, GameProfile), JoinServerError> {
-    conn.write(packet.get()).await?;
-    conn.set_encryption_key(sk);
+    let hello = ServerboundHelloPacket {
+        username: account.username.clone(),
+        public_key: None,
+        profile_id: None,
+    }
+    .get();
+    conn.write(hello).await?;
 
     // While this could technically be abused to cause the client to
     // loop forever, it would have to be a very targeted attack
     loop {
-        match conn.read().await? {
+        let packet = conn.read().await?;
+        match packet {
+            // Servers in offline mode skip this
+            ClientboundLoginPacket::Hello(packet) => {
+                authenticate(&mut conn, account, &packet).await?;
+            }
             ClientboundLoginPacket::GameProfile(packet) => {
                 return Ok((conn.game(), packet.game_profile));
             }
             ClientboundLoginPacket::LoginCompression(packet) => {
                 conn.set_compression_threshold(packet.compression_threshold);
             }
+            ClientboundLoginPacket::CustomQuery(packet) => {
+                answer_query(&mut conn, packet, queries).await?;
+            }
             ClientboundLoginPacket::LoginDisconnect(packet) => {
                 return Err(JoinServerError::Disconnected(packet.reason));
             }
-            packet => {
-                return Err(JoinServerError::UnexpectedPacket(packet));
-            }
         }
     }
 }
 
-/// Start a connection, authenticate and join the server
-pub async fn join_server(
-    addr: &SocketAddr,
+/// Joins the session on Mojang's side and turns on encryption, which online
+/// mode servers ask for
+///
+/// This needs `account` to have an access token and UUID.
+async fn authenticate(
+    conn: &mut ClientLoginConn,
     account: &Account,
-) -> Result<(ClientGameConn, GameProfile), JoinServerError> {
-    let (conn, packet, sk) = almost_join_server(addr, account).await?;
-    finish_joining_server(conn, packet, sk).await
-}
+    encryption_request: &ClientboundHelloPacket,
+) -> Result<(), JoinServerError> {
+    // I warned you
+    let (Some(access_token), Some(uuid)) = (&account.access_token, &account.uuid) else {
+        return Err(JoinServerError::InvalidAccount);
+    };
 
-/// Send a handshake and switch to login
-pub async fn say_hello(
-    addr: &SocketAddr,
-    hello: ServerboundHelloPacket,
-) -> Result<ClientLoginConn, JoinServerError> {
-    let mut conn = Connection::new(addr).await?;
+    // Lettuce do le auth (auth so goofy)
+    // ↑ this file has been copied so much from project to project
+    // that I don't even know who wrote this anymore
+    let encryption_result =
+        azalea_crypto::encrypt(&encryption_request.public_key, &encryption_request.nonce).unwrap();
 
-    // Handshake
-    conn.write(
-        ClientIntentionPacket {
-            protocol_version: PROTOCOL_VERSION,
-            hostname: addr.ip().to_string(),
-            port: addr.port(),
-            intention: ConnectionProtocol::Login,
-        }
-        .get(),
+    // Very secur (I hope)
+    let secret_key = encryption_result.secret_key.to_owned();
+
+    // Here we actually do the auth smh
+    let access_token = access_token.lock().to_owned();
+    sessionserver::join(
+        &access_token,
+        &encryption_request.public_key,
+        &secret_key,
+        uuid,
+        &encryption_request.server_id,
     )
     .await?;
 
-    // This is no laughing matter
-    let mut conn = conn.login();
+    let packet = ServerboundKeyPacket {
+        key_bytes: encryption_result.encrypted_public_key,
+        nonce_or_salt_signature: NonceOrSaltSignature::Nonce(encryption_result.encrypted_nonce),
+    }
+    .get();
+    conn.write(packet).await?;
+    conn.set_encryption_key(secret_key);
 
-    // Hello!
-    conn.write(hello.get()).await?;
+    Ok(())
+}
+
+/// Answers a login plugin request, which servers send to find out if the
+/// client has a mod or to check a proxy's forwarding
+async fn answer_query(
+    conn: &mut ClientLoginConn,
+    query: ClientboundCustomQueryPacket,
+    queries: &dyn CustomQueryHandler,
+) -> Result<(), JoinServerError> {
+    let channel = query.identifier.to_string();
+    let answer = queries.answer(&channel, &query.data).await;
+    debug!(
+        "Answering custom query on {channel}: {}",
+        if answer.is_some() {
+            "understood"
+        } else {
+            "not understood"
+        }
+    );
 
-    Ok(conn)
+    let packet = ServerboundCustomQueryPacket {
+        transaction_id: query.transaction_id,
+        data: answer.map(Into::into),
+    }
+    .get();
+    conn.write(packet).await?;
+    Ok(())
 }
@@ -0,0 +1,176 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::config::LimitsConfig;
+
+/// How many IPs are remembered before the ones that are back to a full bucket
+/// get forgotten
+const MAX_TRACKED_IPS: usize = 4096;
+
+/// Keeps connection spam from piling up
+#[derive(Debug)]
+pub struct ConnectionLimits {
+    config: LimitsConfig,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+    slots: Arc<Semaphore>,
+
+    /// How many connections were turned away so far
+    rejected: AtomicU64,
+}
+
+/// A token bucket, where every connection takes a token
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Why a connection was turned away
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rejection {
+    RateLimited,
+    TooManyConnections,
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::RateLimited => write!(f, "connecting too often"),
+            Self::TooManyConnections => write!(f, "too many open connections"),
+        }
+    }
+}
+
+impl ConnectionLimits {
+    pub fn new(config: &LimitsConfig) -> Self {
+        Self {
+            config: config.clone(),
+            buckets: Mutex::default(),
+            slots: Arc::new(Semaphore::new(config.max_connections)),
+            rejected: AtomicU64::new(0),
+        }
+    }
+
+    /// Lets a connection from `ip` in if it's within the limits. It counts
+    /// towards the open connections until the permit is dropped.
+    pub fn admit(&self, ip: IpAddr) -> Result<OwnedSemaphorePermit, Rejection> {
+        let result = if !self.take_token(ip) {
+            Err(Rejection::RateLimited)
+        } else {
+            self.slots
+                .clone()
+                .try_acquire_owned()
+                .map_err(|_| Rejection::TooManyConnections)
+        };
+
+        if result.is_err() {
+            self.rejected.fetch_add(1, Ordering::Relaxed);
+        }
+        result
+    }
+
+    /// Waits until every connection has been closed
+    pub async fn wait_until_idle(&self) {
+        let _ = self
+            .slots
+            .acquire_many(self.config.max_connections as u32)
+            .await;
+    }
+
+    /// How many connections were turned away so far
+    pub fn rejected(&self) -> u64 {
+        self.rejected.load(Ordering::Relaxed)
+    }
+
+    fn take_token(&self, ip: IpAddr) -> bool {
+        let burst = self.config.burst as f64;
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+
+        if buckets.len() >= MAX_TRACKED_IPS {
+            let refill = self.config.connections_per_second;
+            buckets.retain(|_, bucket| {
+                bucket.tokens + (now - bucket.updated).as_secs_f64() * refill < burst
+            });
+        }
+
+        let bucket = buckets.entry(ip).or_insert(Bucket {
+            tokens: burst,
+            updated: now,
+        });
+        let elapsed = (now - bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.config.connections_per_second).min(burst);
+        bucket.updated = now;
+
+        if bucket.tokens < 1.0 {
+            return false;
+        }
+        bucket.tokens -= 1.0;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn limits(max_connections: usize, connections_per_second: f64, burst: u32) -> ConnectionLimits {
+        ConnectionLimits::new(&LimitsConfig {
+            max_connections,
+            connections_per_second,
+            burst,
+        })
+    }
+
+    #[test]
+    fn allows_a_burst_per_ip() {
+        let limits = limits(100, 0.001, 3);
+        let first = IpAddr::from([203, 0, 113, 5]);
+        let second = IpAddr::from([203, 0, 113, 6]);
+
+        let permits: Vec<_> = (0..3).map(|_| limits.admit(first).unwrap()).collect();
+        assert_eq!(limits.admit(first).unwrap_err(), Rejection::RateLimited);
+        assert!(limits.admit(second).is_ok());
+
+        // Closing connections doesn't give tokens back
+        drop(permits);
+        assert_eq!(limits.admit(first).unwrap_err(), Rejection::RateLimited);
+        assert_eq!(limits.rejected(), 2);
+    }
+
+    #[test]
+    fn refills_over_time() {
+        let limits = limits(100, 50.0, 1);
+        let ip = IpAddr::from([203, 0, 113, 5]);
+
+        assert!(limits.admit(ip).is_ok());
+        assert_eq!(limits.admit(ip).unwrap_err(), Rejection::RateLimited);
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(limits.admit(ip).is_ok());
+    }
+
+    #[test]
+    fn caps_open_connections() {
+        let limits = limits(2, 0.001, 10);
+        let ip = IpAddr::from([203, 0, 113, 5]);
+
+        let first = limits.admit(ip).unwrap();
+        let _second = limits.admit(ip).unwrap();
+        assert_eq!(limits.admit(ip).unwrap_err(), Rejection::TooManyConnections);
+
+        drop(first);
+        assert!(limits.admit(ip).is_ok());
+        assert_eq!(limits.rejected(), 1);
+    }
+}
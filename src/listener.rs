@@ -1,9 +1,14 @@
 use anyhow::Result;
-use tokio::net::TcpListener;
-use tracing::{error, span, Level};
+use std::net::SocketAddr;
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, error, span, warn, Level};
 use tracing_futures::Instrument;
 
-use crate::app::App;
+use crate::{
+    app::App,
+    proxy_protocol,
+    timeouts::{timely, Phase, TimeoutError},
+};
 
 impl App {
     pub async fn listen_for_connections(&self, listener: TcpListener) -> Result<()> {
@@ -20,19 +25,48 @@ impl App {
                         }
                     };
 
-                    // Create a fancy logging context
-                    let span = span!(Level::TRACE, "Connection", remote = %remote);
-
                     // Yeet it off a cliff to teach it how to fly
                     let clone = self.clone();
-                    tokio::spawn(
+                    tokio::spawn(async move {
+                        let mut socket = socket;
+                        let remote = match clone.real_address(&mut socket, remote).await {
+                            Ok(remote) => remote,
+                            Err(e) => {
+                                error!(
+                                    "Failed to read the PROXY protocol header from {remote}: {e}"
+                                );
+                                return;
+                            }
+                        };
+
+                        // Turn spammers away before doing anything for them
+                        let _permit = match clone.limits.admit(remote.ip()) {
+                            Ok(permit) => permit,
+                            Err(rejection) => {
+                                warn!(
+                                    "Rejected connection from {remote}: {rejection} ({} rejected so far)",
+                                    clone.limits.rejected()
+                                );
+                                return;
+                            }
+                        };
+
+                        // Create a fancy logging context
+                        let span = span!(Level::TRACE, "Connection", remote = %remote);
+
                         async move {
-                            if let Err(e) = clone.handle_connection(socket).await {
-                                error!("Error while handling connection: {}", e);
+                            match clone.handle_connection(socket).await {
+                                Ok(()) => {}
+                                // Quiet clients are usually just scanners
+                                Err(e) if e.is::<TimeoutError>() => {
+                                    warn!("Gave up on connection: {e}");
+                                }
+                                Err(e) => error!("Error while handling connection: {}", e),
                             }
                         }
-                        .instrument(span),
-                    );
+                        .instrument(span)
+                        .await
+                    });
                 }
                 Err(e) => {
                     // Again, ??? what
@@ -41,4 +75,19 @@ impl App {
             }
         }
     }
+
+    /// Figures out who's really connecting, which is someone else if a trusted
+    /// load balancer forwarded the connection
+    async fn real_address(&self, socket: &mut TcpStream, peer: SocketAddr) -> Result<SocketAddr> {
+        let config = self.config.get();
+        let proxy_protocol = &config.proxy_protocol;
+        if !proxy_protocol.enabled || !proxy_protocol.trusted.contains(&peer.ip()) {
+            return Ok(peer);
+        }
+
+        let read = proxy_protocol::read_header(socket);
+        let remote = timely(&config.timeouts, Phase::ProxyHeader, read).await??;
+        debug!("{peer} forwarded a connection from {remote:?}");
+        Ok(remote.unwrap_or(peer))
+    }
 }
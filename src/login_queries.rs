@@ -0,0 +1,80 @@
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use std::{collections::HashMap, future::Future, pin::Pin};
+
+/// What a handler answers a query with, where `None` means it wasn't
+/// understood
+pub type QueryAnswer<'a> = Pin<Box<dyn Future<Output = Option<Vec<u8>>> + Send + 'a>>;
+
+/// Answers the custom queries (login plugin requests) a server can send while
+/// the bot logs in, like Forge's handshake or Velocity's forwarding check
+pub trait CustomQueryHandler: Send + Sync {
+    fn answer<'a>(&'a self, channel: &'a str, data: &'a [u8]) -> QueryAnswer<'a>;
+}
+
+type Responder = Box<dyn Fn(&[u8]) -> Option<Vec<u8>> + Send + Sync>;
+
+/// Answers queries on some channels with functions and doesn't understand
+/// the rest, just like a vanilla client
+#[derive(Default)]
+pub struct QueryResponders {
+    responders: HashMap<String, Responder>,
+}
+
+impl QueryResponders {
+    /// Answers every query on a channel the same way, with the base64 answers
+    /// from `login_queries` in the config
+    pub fn from_config(answers: &HashMap<String, String>) -> Result<Self> {
+        let mut responders = Self::default();
+        for (channel, answer) in answers {
+            let answer = BASE64
+                .decode(answer)
+                .with_context(|| format!("The answer to {channel} isn't base64"))?;
+            responders = responders.on(channel, move |_| Some(answer.clone()));
+        }
+        Ok(responders)
+    }
+
+    /// Answers queries on `channel` with whatever `responder` returns
+    pub fn on(
+        mut self,
+        channel: &str,
+        responder: impl Fn(&[u8]) -> Option<Vec<u8>> + Send + Sync + 'static,
+    ) -> Self {
+        self.responders
+            .insert(channel.to_string(), Box::new(responder));
+        self
+    }
+}
+
+impl CustomQueryHandler for QueryResponders {
+    fn answer<'a>(&'a self, channel: &'a str, data: &'a [u8]) -> QueryAnswer<'a> {
+        let answer = self
+            .responders
+            .get(channel)
+            .and_then(|responder| responder(data));
+        Box::pin(async move { answer })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn answers_from_the_config() {
+        let answers = HashMap::from([("fml:handshake".to_string(), "AQI=".to_string())]);
+        let responders = QueryResponders::from_config(&answers).unwrap();
+        assert_eq!(
+            responders.answer("fml:handshake", &[]).await,
+            Some(vec![1, 2])
+        );
+        assert_eq!(responders.answer("velocity:player_info", &[]).await, None);
+    }
+
+    #[test]
+    fn rejects_answers_that_arent_base64() {
+        let answers = HashMap::from([("fml:handshake".to_string(), "not base64!".to_string())]);
+        assert!(QueryResponders::from_config(&answers).is_err());
+    }
+}
@@ -1,24 +1,85 @@
-use anyhow::{Context, Result};
-use clap::Parser;
+use anyhow::{bail as yeet, Context, Result};
+use azalea_client::Account;
+use clap::{Args, Parser, Subcommand};
 use std::path::PathBuf;
-use tracing::info;
+use tracing::{info, warn};
 
-use crate::{app::App, config::Config};
+use crate::{
+    app::App,
+    config::{AccountKind, Config, SharedConfig},
+    inspect::InspectArgs,
+    ping::PingArgs,
+};
 
+mod api;
 mod app;
+mod capture;
+mod chat_log;
 mod config;
 mod conn;
+mod events;
+mod forwarding;
+mod inspect;
 mod join;
+mod limits;
 mod listener;
 mod logging;
+mod login_queries;
+mod notifications;
+mod outbound;
+mod ping;
+mod proxy_protocol;
+mod replay;
+mod server_auth;
+mod store;
+mod timeouts;
 
 #[derive(Parser)]
 struct CliArgs {
-    #[arg(short, long, default_value = "config.toml")]
+    #[arg(short, long, default_value = "config.toml", global = true)]
     config_path: PathBuf,
 
-    #[arg(long, default_value = "false")]
+    /// The old way to write the default config, now `init-config --force`
+    #[arg(long, hide = true)]
     overwrite_config: bool,
+
+    /// What to do, which is running the proxy unless told otherwise
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run the proxy
+    Run,
+
+    /// Write a config file with default values
+    InitConfig(InitConfigArgs),
+
+    /// Check that the config file can be loaded
+    CheckConfig,
+
+    /// Log into a Microsoft account and cache the tokens for later
+    Login(LoginArgs),
+
+    /// Ask a server for its status like the server list does
+    Ping(PingArgs),
+
+    /// Print the packets in a capture file
+    Inspect(InspectArgs),
+}
+
+#[derive(Args)]
+struct InitConfigArgs {
+    /// Replace the config file if there already is one
+    #[arg(long)]
+    force: bool,
+}
+
+#[derive(Args)]
+struct LoginArgs {
+    /// The account's email, or the one from the config if left out
+    account: Option<String>,
 }
 
 #[tokio::main]
@@ -29,17 +90,26 @@ async fn main() -> Result<()> {
     // Set up a global tracing listener
     logging::setup().context("Failed to set up logging")?;
 
-    // Create a config file if it doesn't exist or if the user wants to overwrite it
-    if !config_path.exists() || args.overwrite_config {
-        if !config_path.exists() {
-            info!("Config file does not exist, creating one with default values and exiting");
-        }
-        if args.overwrite_config {
-            info!("Overwriting config file with default values and exiting");
-        }
-        let config = Config::default();
-        config.save(&config_path).await?;
-        return Ok(());
+    if args.overwrite_config {
+        warn!("--overwrite-config is deprecated, use `init-config --force` instead");
+        return init_config(&config_path, true).await;
+    }
+
+    match args.command.unwrap_or(Command::Run) {
+        Command::Run => run(config_path).await,
+        Command::InitConfig(args) => init_config(&config_path, args.force).await,
+        Command::CheckConfig => check_config(&config_path).await,
+        Command::Login(args) => login(&config_path, args.account).await,
+        Command::Ping(args) => ping::ping(args).await,
+        Command::Inspect(args) => inspect::inspect(args).await,
+    }
+}
+
+async fn run(config_path: PathBuf) -> Result<()> {
+    // Create a config file if it doesn't exist
+    if !config_path.exists() {
+        info!("Config file does not exist, creating one with default values and exiting");
+        return init_config(&config_path, false).await;
     }
 
     // Load the config
@@ -48,7 +118,7 @@ async fn main() -> Result<()> {
         .context("Failed to load the config")?;
 
     // Initialize the app
-    let mut app = App::init(config)
+    let mut app = App::init(SharedConfig::new(config_path, config))
         .await
         .context("Failed to initialize the app")?;
 
@@ -57,3 +127,49 @@ async fn main() -> Result<()> {
 
     Ok(())
 }
+
+async fn init_config(config_path: &PathBuf, force: bool) -> Result<()> {
+    if config_path.exists() && !force {
+        yeet!(
+            "{} already exists, use --force to overwrite it",
+            config_path.display()
+        );
+    }
+
+    Config::default().save(config_path).await?;
+    info!("Wrote the default config to {}", config_path.display());
+    Ok(())
+}
+
+async fn check_config(config_path: &PathBuf) -> Result<()> {
+    Config::load(config_path)
+        .await
+        .context("Failed to load the config")?;
+    info!("{} looks good", config_path.display());
+    Ok(())
+}
+
+async fn login(config_path: &PathBuf, account: Option<String>) -> Result<()> {
+    let account = match account {
+        Some(account) => account,
+        None => {
+            let config = Config::load(config_path)
+                .await
+                .context("Failed to load the config for the account")?;
+            if config.account_kind == AccountKind::Offline {
+                info!(
+                    "{} is an offline account, there's nothing to log into",
+                    config.account
+                );
+                return Ok(());
+            }
+            config.account
+        }
+    };
+
+    // Asks to authenticate in the browser unless the tokens are cached
+    let account = Account::microsoft(&account).await?;
+    let uuid = account.uuid.context("The account has no UUID")?;
+    info!("Logged in as {} ({uuid})", account.username);
+    Ok(())
+}
@@ -0,0 +1,302 @@
+use anyhow::{bail as yeet, Result};
+use serde_json::json;
+use std::{process::Stdio, time::Duration};
+use tokio::{
+    fs::OpenOptions,
+    io::AsyncWriteExt,
+    process::Command,
+    sync::{
+        broadcast::error::RecvError,
+        mpsc::{self, UnboundedSender},
+    },
+};
+use tracing::{error, warn};
+
+use crate::{
+    app::App,
+    config::{SinkConfig, SinkKind, WebhookFormat},
+    events::ProxyEvent,
+};
+
+/// How long a webhook gets to answer before we move on
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A sink with its own queue, so a slow one doesn't hold up the others
+struct SinkWorker {
+    kind: SinkKind,
+    queue: UnboundedSender<ProxyEvent>,
+}
+
+impl App {
+    /// Sends every event to the sinks in the config
+    ///
+    /// The sinks are looked up for every event, so changes to them apply
+    /// after a reload. Each sink gets the events in order, at its own pace.
+    pub fn spawn_notifications(&self) {
+        let app = self.clone();
+        tokio::spawn(async move {
+            let client = reqwest::Client::builder()
+                .timeout(WEBHOOK_TIMEOUT)
+                .build()
+                .unwrap_or_default();
+
+            let mut workers: Vec<SinkWorker> = vec![];
+            let mut receiver = app.events.subscribe();
+            loop {
+                let event = match receiver.recv().await {
+                    Ok(event) => event,
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("Notifications skipped {skipped} events");
+                        continue;
+                    }
+                    Err(RecvError::Closed) => return,
+                };
+
+                let config = app.config.get();
+                let sinks = &config.notifications.sinks;
+
+                // Sinks removed by a reload finish what they have and stop
+                workers.retain(|worker| sinks.iter().any(|sink| sink.kind == worker.kind));
+
+                for sink in sinks.iter().filter(|sink| wants(sink, &event)) {
+                    let index = match workers.iter().position(|worker| worker.kind == sink.kind) {
+                        Some(index) => index,
+                        None => {
+                            workers.push(spawn_sink(client.clone(), sink.clone()));
+                            workers.len() - 1
+                        }
+                    };
+                    let _ = workers[index].queue.send(event.clone());
+                }
+            }
+        });
+    }
+}
+
+/// Sends everything queued for a sink until the queue is dropped
+fn spawn_sink(client: reqwest::Client, sink: SinkConfig) -> SinkWorker {
+    let (queue, mut events) = mpsc::unbounded_channel::<ProxyEvent>();
+    let kind = sink.kind.clone();
+    tokio::spawn(async move {
+        while let Some(event) = events.recv().await {
+            if let Err(err) = notify(&client, &sink.kind, &event).await {
+                error!(
+                    "Failed to send a notification to {}: {err}",
+                    describe(&sink)
+                );
+            }
+        }
+    });
+    SinkWorker { kind, queue }
+}
+
+fn wants(sink: &SinkConfig, event: &ProxyEvent) -> bool {
+    if sink.events.is_empty() {
+        return !event.kind.is_opt_in();
+    }
+    sink.events.iter().any(|name| name == event.kind.name())
+}
+
+async fn notify(client: &reqwest::Client, sink: &SinkKind, event: &ProxyEvent) -> Result<()> {
+    match sink {
+        SinkKind::Webhook { url, format } => {
+            let body = match format {
+                WebhookFormat::Json => serde_json::to_value(event)?,
+                WebhookFormat::Discord => json!({ "content": event.kind.to_string() }),
+            };
+            let response = client.post(url).json(&body).send().await?;
+            if !response.status().is_success() {
+                yeet!("the webhook answered with {}", response.status());
+            }
+        }
+        SinkKind::File { path } => {
+            let line = serde_json::to_string(event)? + "\n";
+            let mut file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .await?;
+            file.write_all(line.as_bytes()).await?;
+        }
+        SinkKind::Command { command, args } => {
+            let mut child = Command::new(command)
+                .args(args)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::null())
+                .spawn()?;
+            if let Some(mut stdin) = child.stdin.take() {
+                stdin.write_all(&serde_json::to_vec(event)?).await?;
+            }
+
+            // Slow commands shouldn't hold up the other notifications
+            let command = command.clone();
+            tokio::spawn(async move {
+                match child.wait().await {
+                    Ok(status) if !status.success() => {
+                        warn!("Notification command {command} exited with {status}");
+                    }
+                    Ok(_) => {}
+                    Err(err) => warn!("Failed to wait for notification command {command}: {err}"),
+                }
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Which sink this is, for logs
+fn describe(sink: &SinkConfig) -> String {
+    match &sink.kind {
+        SinkKind::Webhook { url, .. } => format!("webhook {url}"),
+        SinkKind::File { path } => format!("file {}", path.display()),
+        SinkKind::Command { command, .. } => format!("command {command}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use serde_json::Value;
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncReadExt, BufReader},
+        net::TcpListener,
+    };
+
+    use super::*;
+    use crate::{
+        chat_log::{ChatKind, ChatMessage},
+        events::EventKind,
+    };
+
+    fn event(kind: EventKind) -> ProxyEvent {
+        ProxyEvent {
+            time: Utc::now(),
+            kind,
+        }
+    }
+
+    fn died() -> ProxyEvent {
+        event(EventKind::Died {
+            message: "LiveOvergoober fell from a high place".to_string(),
+        })
+    }
+
+    fn webhook(url: &str, events: &[&str]) -> SinkConfig {
+        SinkConfig {
+            kind: SinkKind::Webhook {
+                url: url.to_string(),
+                format: WebhookFormat::Json,
+            },
+            events: events.iter().map(|name| name.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn filters_events() {
+        let chat = event(EventKind::Chat {
+            message: ChatMessage {
+                time: Utc::now(),
+                kind: ChatKind::Player,
+                sender: None,
+                sender_name: Some("Alice".to_string()),
+                message: "<Alice> hi".to_string(),
+            },
+        });
+
+        let everything = webhook("http://localhost", &[]);
+        assert!(wants(&everything, &died()));
+        assert!(!wants(&everything, &chat));
+
+        let deaths = webhook("http://localhost", &["died"]);
+        assert!(wants(&deaths, &died()));
+        assert!(!wants(&deaths, &chat));
+
+        let chat_only = webhook("http://localhost", &["chat"]);
+        assert!(!wants(&chat_only, &died()));
+        assert!(wants(&chat_only, &chat));
+    }
+
+    #[tokio::test]
+    async fn posts_to_webhooks() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut reader = BufReader::new(stream);
+
+            let mut line = String::new();
+            reader.read_line(&mut line).await.unwrap();
+            assert_eq!(line.trim_end(), "POST /hook HTTP/1.1");
+
+            let mut length = 0;
+            loop {
+                line.clear();
+                reader.read_line(&mut line).await.unwrap();
+                let header = line.trim_end().to_lowercase();
+                if header.is_empty() {
+                    break;
+                }
+                if let Some(value) = header.strip_prefix("content-length:") {
+                    length = value.trim().parse().unwrap();
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).await.unwrap();
+
+            let stream = reader.get_mut();
+            stream
+                .write_all(b"HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n")
+                .await
+                .unwrap();
+            serde_json::from_slice::<Value>(&body).unwrap()
+        });
+
+        let sink = webhook(&url, &[]);
+        notify(&reqwest::Client::new(), &sink.kind, &died())
+            .await
+            .unwrap();
+
+        let body = server.await.unwrap();
+        assert_eq!(body["type"], "died");
+        assert_eq!(body["message"], "LiveOvergoober fell from a high place");
+    }
+
+    #[tokio::test]
+    async fn slow_sinks_dont_hold_up_the_others() {
+        // A webhook that takes the request and never answers
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let _server = tokio::spawn(async move {
+            let (_stream, _) = listener.accept().await.unwrap();
+            std::future::pending::<()>().await;
+        });
+        let path = std::env::temp_dir().join(format!("events-test-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let client = reqwest::Client::new();
+        let slow = spawn_sink(client.clone(), webhook(&url, &[]));
+        let file = spawn_sink(
+            client,
+            SinkConfig {
+                kind: SinkKind::File { path: path.clone() },
+                events: vec![],
+            },
+        );
+        slow.queue.send(died()).unwrap();
+        file.queue.send(died()).unwrap();
+
+        let written = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                match tokio::fs::read_to_string(&path).await {
+                    Ok(contents) if !contents.is_empty() => return contents,
+                    _ => tokio::time::sleep(Duration::from_millis(10)).await,
+                }
+            }
+        })
+        .await
+        .unwrap();
+        assert!(written.contains("\"died\""));
+        let _ = std::fs::remove_file(&path);
+    }
+}
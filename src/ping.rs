@@ -0,0 +1,90 @@
+use anyhow::{bail as yeet, Context, Result};
+use azalea_protocol::packets::{
+    handshake::client_intention_packet::ClientIntentionPacket,
+    status::{
+        serverbound_ping_request_packet::ServerboundPingRequestPacket,
+        serverbound_status_request_packet::ServerboundStatusRequestPacket, ClientboundStatusPacket,
+    },
+    ConnectionProtocol, PROTOCOL_VERSION,
+};
+use clap::Args;
+use std::time::{Duration, Instant};
+
+use crate::conn::{ClientHandshakeConn, Connection};
+
+/// The port servers listen on unless told otherwise
+const DEFAULT_PORT: u16 = 25565;
+
+/// Asks a server for its status like the server list does
+#[derive(Debug, Args)]
+pub struct PingArgs {
+    /// The server's `host:port`, where the port defaults to 25565
+    server: String,
+
+    /// Seconds to wait for the server before giving up
+    #[arg(long, default_value = "5")]
+    timeout: u64,
+}
+
+pub async fn ping(args: PingArgs) -> Result<()> {
+    let timeout = Duration::from_secs(args.timeout);
+    tokio::time::timeout(timeout, ping_server(&args.server))
+        .await
+        .with_context(|| format!("{} didn't answer in time", args.server))?
+}
+
+async fn ping_server(server: &str) -> Result<()> {
+    let (host, port) = match server.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse().context("Invalid port")?),
+        None => (server, DEFAULT_PORT),
+    };
+    let addr = tokio::net::lookup_host((host, port))
+        .await?
+        .next()
+        .with_context(|| format!("Couldn't find {host}"))?;
+
+    let mut conn: ClientHandshakeConn = Connection::new(&addr).await?;
+    conn.write(
+        ClientIntentionPacket {
+            protocol_version: PROTOCOL_VERSION,
+            hostname: host.to_string(),
+            port,
+            intention: ConnectionProtocol::Status,
+        }
+        .get(),
+    )
+    .await?;
+
+    let mut conn = conn.status();
+    conn.write(ServerboundStatusRequestPacket {}.get()).await?;
+    let status = match conn.read().await? {
+        ClientboundStatusPacket::StatusResponse(status) => status,
+        packet => yeet!("Expected a status response, got {packet:?}"),
+    };
+
+    let sent = Instant::now();
+    conn.write(ServerboundPingRequestPacket { time: 0 }.get())
+        .await?;
+    match conn.read().await? {
+        ClientboundStatusPacket::PongResponse(_) => {}
+        packet => yeet!("Expected a pong, got {packet:?}"),
+    }
+    let latency = sent.elapsed();
+
+    println!("{addr}");
+    println!("  {}", status.description);
+    println!(
+        "  Version: {} (protocol {}, we speak {PROTOCOL_VERSION})",
+        status.version.name, status.version.protocol
+    );
+    println!(
+        "  Players: {}/{}",
+        status.players.online, status.players.max
+    );
+    for player in &status.players.sample {
+        println!("    {}", player.name);
+    }
+    println!("  Latency: {}ms", latency.as_millis());
+
+    Ok(())
+}
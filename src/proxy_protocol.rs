@@ -0,0 +1,193 @@
+use std::{
+    io::{self, ErrorKind},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+};
+use tokio::{io::AsyncReadExt, net::TcpStream};
+
+/// What every version 2 header starts with
+const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+
+/// Version 1 headers can't be longer than this, including the line break
+const V1_MAX_LENGTH: usize = 107;
+
+/// Reads a PROXY protocol (version 1 or 2) header from a connection a load
+/// balancer made, returning the address of the client behind it
+///
+/// The balancer may not know the client's address, like when it's checking if
+/// we're still up, in which case this returns `None`.
+pub async fn read_header(stream: &mut TcpStream) -> io::Result<Option<SocketAddr>> {
+    // Both versions are at least this long
+    let mut start = [0; 12];
+    stream.read_exact(&mut start).await?;
+
+    if start == V2_SIGNATURE {
+        read_v2(stream).await
+    } else if start.starts_with(b"PROXY ") {
+        read_v1(stream, &start).await
+    } else {
+        Err(invalid("missing PROXY protocol header"))
+    }
+}
+
+async fn read_v1(stream: &mut TcpStream, start: &[u8]) -> io::Result<Option<SocketAddr>> {
+    let mut line = start.to_vec();
+    while !line.ends_with(b"\r\n") {
+        if line.len() >= V1_MAX_LENGTH {
+            return Err(invalid("PROXY protocol header is too long"));
+        }
+        line.push(stream.read_u8().await?);
+    }
+
+    let line = String::from_utf8_lossy(&line);
+    let parts = line.trim_end().split(' ').collect::<Vec<_>>();
+    match parts.as_slice() {
+        ["PROXY", "TCP4" | "TCP6", source, _, port, _] => {
+            let ip = source
+                .parse::<IpAddr>()
+                .map_err(|_| invalid("invalid source address in PROXY protocol header"))?;
+            let port = port
+                .parse()
+                .map_err(|_| invalid("invalid source port in PROXY protocol header"))?;
+            Ok(Some(SocketAddr::new(ip, port)))
+        }
+        ["PROXY", "UNKNOWN", ..] => Ok(None),
+        _ => Err(invalid("invalid PROXY protocol header")),
+    }
+}
+
+async fn read_v2(stream: &mut TcpStream) -> io::Result<Option<SocketAddr>> {
+    let version_command = stream.read_u8().await?;
+    let family = stream.read_u8().await?;
+    let length = stream.read_u16().await? as usize;
+
+    // Whatever comes after the addresses (like TLVs) has to be read too
+    let mut addresses = vec![0; length];
+    stream.read_exact(&mut addresses).await?;
+
+    if version_command >> 4 != 2 {
+        return Err(invalid("unsupported PROXY protocol version"));
+    }
+    // The balancer connecting on its own behalf
+    if version_command & 0x0f == 0 {
+        return Ok(None);
+    }
+
+    match family >> 4 {
+        // IPv4
+        1 if addresses.len() >= 12 => {
+            let ip = Ipv4Addr::new(addresses[0], addresses[1], addresses[2], addresses[3]);
+            let port = u16::from_be_bytes([addresses[8], addresses[9]]);
+            Ok(Some(SocketAddr::new(ip.into(), port)))
+        }
+        // IPv6
+        2 if addresses.len() >= 36 => {
+            let mut ip = [0; 16];
+            ip.copy_from_slice(&addresses[0..16]);
+            let port = u16::from_be_bytes([addresses[32], addresses[33]]);
+            Ok(Some(SocketAddr::new(Ipv6Addr::from(ip).into(), port)))
+        }
+        // Unix sockets and anything unspecified
+        _ => Ok(None),
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{io::AsyncWriteExt, net::TcpListener};
+
+    use super::*;
+
+    /// Sends `header` and whatever follows it like a load balancer would, and
+    /// reads the header back on the other end
+    async fn read(header: &[u8]) -> io::Result<Option<SocketAddr>> {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut balancer = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (mut stream, _) = listener.accept().await.unwrap();
+
+        balancer.write_all(header).await.unwrap();
+        balancer.write_all(b"\x10\x00").await.unwrap();
+        balancer.shutdown().await.unwrap();
+
+        let result = read_header(&mut stream).await;
+        if result.is_ok() {
+            // The header was read exactly, leaving the Minecraft packet alone
+            assert_eq!(stream.read_u16().await.unwrap(), 0x1000);
+        }
+        result
+    }
+
+    fn v2(command: u8, family: u8, addresses: &[u8]) -> Vec<u8> {
+        let mut header = V2_SIGNATURE.to_vec();
+        header.push(0x20 | command);
+        header.push(family);
+        header.extend((addresses.len() as u16).to_be_bytes());
+        header.extend(addresses);
+        header
+    }
+
+    #[tokio::test]
+    async fn reads_v1_headers() {
+        let addr = read(b"PROXY TCP4 203.0.113.5 192.0.2.1 51234 25565\r\n").await;
+        assert_eq!(addr.unwrap(), Some("203.0.113.5:51234".parse().unwrap()));
+
+        let addr = read(b"PROXY TCP6 2001:db8::5 2001:db8::1 51234 25565\r\n").await;
+        assert_eq!(addr.unwrap(), Some("[2001:db8::5]:51234".parse().unwrap()));
+
+        assert_eq!(read(b"PROXY UNKNOWN\r\n").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn rejects_bad_v1_headers() {
+        assert!(read(b"PROXY TCP4 nowhere 192.0.2.1 51234 25565\r\n")
+            .await
+            .is_err());
+        assert!(read(b"PROXY TCP4 203.0.113.5 192.0.2.1 port 25565\r\n")
+            .await
+            .is_err());
+        assert!(read(b"PROXY UDP4 203.0.113.5 192.0.2.1 51234 25565\r\n")
+            .await
+            .is_err());
+
+        let long = format!("PROXY UNKNOWN {}\r\n", "a".repeat(V1_MAX_LENGTH));
+        assert!(read(long.as_bytes()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn reads_v2_headers() {
+        let mut ipv4 = vec![203, 0, 113, 5, 192, 0, 2, 1];
+        ipv4.extend(51234u16.to_be_bytes());
+        ipv4.extend(25565u16.to_be_bytes());
+        let addr = read(&v2(1, 0x11, &ipv4)).await;
+        assert_eq!(addr.unwrap(), Some("203.0.113.5:51234".parse().unwrap()));
+
+        // With a TLV after the addresses
+        let mut ipv6 = "2001:db8::5".parse::<Ipv6Addr>().unwrap().octets().to_vec();
+        ipv6.extend("2001:db8::1".parse::<Ipv6Addr>().unwrap().octets());
+        ipv6.extend(51234u16.to_be_bytes());
+        ipv6.extend(25565u16.to_be_bytes());
+        ipv6.extend([0x04, 0x00, 0x00]);
+        let addr = read(&v2(1, 0x21, &ipv6)).await;
+        assert_eq!(addr.unwrap(), Some("[2001:db8::5]:51234".parse().unwrap()));
+
+        // Health checks and families without an address
+        assert_eq!(read(&v2(0, 0x11, &ipv4)).await.unwrap(), None);
+        assert_eq!(read(&v2(1, 0x31, &[0; 216])).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn rejects_bad_v2_headers() {
+        let mut header = v2(1, 0x11, &[0; 12]);
+        header[12] = 0x11;
+        assert!(read(&header).await.is_err());
+
+        assert!(read(b"\x10\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00")
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,256 @@
+use anyhow::{Context, Result};
+use azalea_auth::game_profile::GameProfile;
+use azalea_protocol::{
+    packets::{
+        game::ClientboundGamePacket,
+        login::clientbound_game_profile_packet::ClientboundGameProfilePacket, ProtocolPacket,
+        PROTOCOL_VERSION,
+    },
+    write::packet_encoder,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::{
+    collections::HashSet,
+    fmt::Debug,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+use tokio::{
+    fs::File,
+    io::{AsyncWriteExt, BufWriter},
+    sync::mpsc::{self, UnboundedReceiver, UnboundedSender},
+    task::JoinHandle,
+};
+use tracing::warn;
+use uuid::Uuid;
+use zip::{write::FileOptions, ZipWriter};
+
+/// The Minecraft version recordings are made in, which has to be updated
+/// along with azalea
+const MC_VERSION: &str = match PROTOCOL_VERSION {
+    761 => "1.19.3",
+    _ => panic!("The Minecraft version for this protocol version is missing"),
+};
+
+/// Records the packets of a session into a `.mcpr` file for ReplayMod
+///
+/// Packets are written to a `.tmcpr` file next to the recording as they come
+/// in, which is packed up together with the metadata once the recording is
+/// finished.
+#[derive(Debug)]
+pub struct Recorder {
+    path: PathBuf,
+    server_name: String,
+    date: DateTime<Utc>,
+    started: Instant,
+
+    /// The entity id of the player the recording is made as
+    self_id: Option<u32>,
+
+    /// Every player that showed up in the recording
+    players: HashSet<Uuid>,
+
+    entries: UnboundedSender<(u32, Vec<u8>)>,
+    writer: JoinHandle<Result<()>>,
+}
+
+/// What ReplayMod wants to know about a recording
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct MetaData {
+    singleplayer: bool,
+    server_name: String,
+    duration: u32,
+    date: i64,
+    mcversion: &'static str,
+    file_format: &'static str,
+    file_format_version: u32,
+    protocol: u32,
+    generator: String,
+    self_id: i32,
+    players: Vec<Uuid>,
+}
+
+impl Recorder {
+    /// Starts a new recording in `directory`, as the player with `profile`
+    pub async fn start(
+        directory: &Path,
+        server_name: String,
+        profile: &GameProfile,
+    ) -> Result<Self> {
+        tokio::fs::create_dir_all(directory)
+            .await
+            .context("Failed to create the replay directory")?;
+
+        let date = Utc::now();
+        let path = directory.join(format!("{}.mcpr", date.format("%Y-%m-%d_%H-%M-%S")));
+        let file = File::create(path.with_extension("tmcpr")).await?;
+
+        let (entries, receiver) = mpsc::unbounded_channel();
+        let mut recorder = Self {
+            path,
+            server_name,
+            date,
+            started: Instant::now(),
+            self_id: None,
+            players: HashSet::new(),
+            entries,
+            writer: tokio::spawn(write_entries(file, receiver)),
+        };
+
+        // Recordings start right at the end of the login
+        let login = ClientboundGameProfilePacket {
+            game_profile: profile.clone(),
+        };
+        recorder.write(&login.get());
+
+        Ok(recorder)
+    }
+
+    /// Adds a packet from the server to the recording
+    pub fn record(&mut self, packet: &ClientboundGamePacket) {
+        match packet {
+            ClientboundGamePacket::Login(packet) => self.self_id = Some(packet.player_id),
+            ClientboundGamePacket::AddPlayer(packet) => {
+                self.players.insert(packet.uuid);
+            }
+            _ => {}
+        }
+        self.write(packet);
+    }
+
+    fn write<P: ProtocolPacket + Debug>(&mut self, packet: &P) {
+        let data = match packet_encoder(packet) {
+            Ok(data) => data,
+            Err(err) => {
+                warn!("Failed to record a packet: {err}");
+                return;
+            }
+        };
+
+        let time = self.started.elapsed().as_millis() as u32;
+        let _ = self.entries.send((time, data));
+    }
+
+    /// Stops recording and packs everything up, returning where the
+    /// recording ended up
+    pub async fn finish(self) -> Result<PathBuf> {
+        let duration = self.started.elapsed().as_millis() as u32;
+        drop(self.entries);
+        self.writer.await??;
+
+        let meta_data = MetaData {
+            singleplayer: false,
+            server_name: self.server_name,
+            duration,
+            date: self.date.timestamp_millis(),
+            mcversion: MC_VERSION,
+            file_format: "MCPR",
+            file_format_version: 14,
+            protocol: PROTOCOL_VERSION,
+            generator: format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+            self_id: self.self_id.map_or(-1, |id| id as i32),
+            players: self.players.into_iter().collect(),
+        };
+
+        let path = self.path;
+        tokio::task::spawn_blocking(move || pack(&path, &meta_data).map(|_| path)).await?
+    }
+}
+
+/// Writes packets to the `.tmcpr` file until the recorder is done with it
+async fn write_entries(file: File, mut entries: UnboundedReceiver<(u32, Vec<u8>)>) -> Result<()> {
+    let mut file = BufWriter::new(file);
+    while let Some((time, data)) = entries.recv().await {
+        file.write_u32(time).await?;
+        file.write_u32(data.len() as u32).await?;
+        file.write_all(&data).await?;
+    }
+    file.flush().await?;
+    Ok(())
+}
+
+/// Puts the packets and the metadata into the `.mcpr` archive
+fn pack(path: &Path, meta_data: &MetaData) -> Result<()> {
+    let packets_path = path.with_extension("tmcpr");
+    let packets = fs::read(&packets_path)?;
+
+    let mut zip = ZipWriter::new(fs::File::create(path)?);
+    zip.start_file("recording.tmcpr", FileOptions::default())?;
+    zip.write_all(&packets)?;
+    zip.start_file("metaData.json", FileOptions::default())?;
+    zip.write_all(&serde_json::to_vec(meta_data)?)?;
+    zip.finish()?;
+
+    fs::remove_file(packets_path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use zip::ZipArchive;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn packs_recordings() {
+        let directory = std::env::temp_dir().join(format!("replay-test-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("recording.mcpr");
+
+        let file = File::create(path.with_extension("tmcpr")).await.unwrap();
+        let (entries, receiver) = mpsc::unbounded_channel();
+        let writer = tokio::spawn(write_entries(file, receiver));
+        entries.send((0, vec![0x02, 0xaa])).unwrap();
+        entries.send((1500, vec![0x28])).unwrap();
+        drop(entries);
+        writer.await.unwrap().unwrap();
+
+        let player = Uuid::from_u128(1);
+        let meta_data = MetaData {
+            singleplayer: false,
+            server_name: "default".to_string(),
+            duration: 1500,
+            date: 0,
+            mcversion: MC_VERSION,
+            file_format: "MCPR",
+            file_format_version: 14,
+            protocol: PROTOCOL_VERSION,
+            generator: "test".to_string(),
+            self_id: 42,
+            players: vec![player],
+        };
+        pack(&path, &meta_data).unwrap();
+        assert!(!path.with_extension("tmcpr").exists());
+
+        let mut zip = ZipArchive::new(fs::File::open(&path).unwrap()).unwrap();
+        let mut packets = vec![];
+        zip.by_name("recording.tmcpr")
+            .unwrap()
+            .read_to_end(&mut packets)
+            .unwrap();
+        assert_eq!(
+            packets,
+            [
+                &[0, 0, 0, 0, 0, 0, 0, 2, 0x02, 0xaa][..],
+                &[0, 0, 0x05, 0xdc, 0, 0, 0, 1, 0x28],
+            ]
+            .concat()
+        );
+
+        let meta_data: serde_json::Value =
+            serde_json::from_reader(zip.by_name("metaData.json").unwrap()).unwrap();
+        assert_eq!(meta_data["serverName"], "default");
+        assert_eq!(meta_data["mcversion"], MC_VERSION);
+        assert_eq!(meta_data["fileFormat"], "MCPR");
+        assert_eq!(meta_data["protocol"], PROTOCOL_VERSION);
+        assert_eq!(meta_data["selfId"], 42);
+        assert_eq!(meta_data["players"][0], player.to_string());
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,91 @@
+use anyhow::{Context, Result};
+use reqwest::StatusCode;
+use rsa::{pkcs8::EncodePublicKey, Pkcs1v15Encrypt, RsaPrivateKey};
+use serde::Deserialize;
+use uuid::Uuid;
+
+/// How long the key is, same as a vanilla server's
+const KEY_BITS: usize = 1024;
+
+/// The key pair clients encrypt the shared secret with while logging in, made
+/// once per run like vanilla servers do
+pub struct ServerKey {
+    private: RsaPrivateKey,
+
+    /// The public key in the DER format clients expect
+    public: Vec<u8>,
+}
+
+/// Who a client proved to be with Mojang
+#[derive(Debug, Clone, Deserialize)]
+pub struct VerifiedProfile {
+    pub id: Uuid,
+    pub name: String,
+}
+
+impl ServerKey {
+    /// Makes a new key pair, which takes a moment
+    pub fn generate() -> Result<Self> {
+        let private = RsaPrivateKey::new(&mut rand::thread_rng(), KEY_BITS)?;
+        let public = private
+            .to_public_key()
+            .to_public_key_der()
+            .context("Failed to encode the public key")?
+            .into_vec();
+        Ok(Self { private, public })
+    }
+
+    pub fn public_der(&self) -> &[u8] {
+        &self.public
+    }
+
+    /// Decrypts something a client encrypted with our public key
+    pub fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>> {
+        Ok(self.private.decrypt(Pkcs1v15Encrypt, data)?)
+    }
+}
+
+/// Asks Mojang whether `username` joined with this shared secret, which the
+/// client can only have done while logged into that account
+pub async fn has_joined(
+    username: &str,
+    shared_secret: &[u8],
+    public_key: &[u8],
+) -> Result<Option<VerifiedProfile>> {
+    let server_hash =
+        azalea_crypto::hex_digest(&azalea_crypto::digest_data(b"", public_key, shared_secret));
+    let response = reqwest::Client::new()
+        .get("https://sessionserver.mojang.com/session/minecraft/hasJoined")
+        .query(&[("username", username), ("serverId", &server_hash)])
+        .send()
+        .await?
+        .error_for_status()?;
+
+    // Mojang has nothing to say about clients that didn't join
+    if response.status() == StatusCode::NO_CONTENT {
+        return Ok(None);
+    }
+    Ok(Some(response.json().await?))
+}
+
+#[cfg(test)]
+mod tests {
+    use rsa::{pkcs8::DecodePublicKey, PublicKey, RsaPublicKey};
+
+    use super::*;
+
+    #[test]
+    fn decrypts_what_clients_encrypt() {
+        let key = ServerKey::generate().unwrap();
+
+        // What a client does with the key from the encryption request
+        let public = RsaPublicKey::from_public_key_der(key.public_der()).unwrap();
+        let secret = [7; 16];
+        let encrypted = public
+            .encrypt(&mut rand::thread_rng(), Pkcs1v15Encrypt, &secret)
+            .unwrap();
+
+        assert_eq!(key.decrypt(&encrypted).unwrap(), secret);
+        assert!(key.decrypt(&secret).is_err());
+    }
+}
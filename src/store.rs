@@ -0,0 +1,172 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+use uuid::Uuid;
+
+use crate::app::SafetyTrigger;
+
+/// How many queue positions and deaths are remembered
+const MAX_HISTORY: usize = 100;
+
+/// Everything about the proxy that should survive a restart
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PersistedState {
+    /// Whether the bot should be on the server, so it reconnects after a
+    /// restart
+    pub bot_running: bool,
+
+    /// The server the bot was moved to, if it isn't on the default one
+    pub server: Option<String>,
+
+    /// The safety trigger that logged the bot out, until the player has
+    /// seen it
+    pub tripped: Option<SafetyTrigger>,
+
+    /// Whether recording was started with `/proxy replay start`
+    pub recording: bool,
+
+    pub last_position: Option<LastPosition>,
+
+    /// The account the bot last logged in as
+    pub auth: Option<AuthStatus>,
+
+    /// The bot's recent queue positions, oldest first
+    pub queue: Vec<QueueEntry>,
+
+    /// The bot's recent deaths, oldest first
+    pub deaths: Vec<Death>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LastPosition {
+    pub server: String,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthStatus {
+    pub account: String,
+    pub username: String,
+    pub uuid: Option<Uuid>,
+    pub time: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueueEntry {
+    pub time: DateTime<Utc>,
+    pub position: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Death {
+    pub time: DateTime<Utc>,
+    pub message: String,
+    pub position: Option<LastPosition>,
+}
+
+impl PersistedState {
+    pub fn push_queue(&mut self, position: u32) {
+        push_capped(
+            &mut self.queue,
+            QueueEntry {
+                time: Utc::now(),
+                position,
+            },
+        );
+    }
+
+    /// Remembers a death at where the bot was when it happened, which can be
+    /// newer than `last_position`
+    pub fn push_death(&mut self, message: String, position: Option<LastPosition>) {
+        let death = Death {
+            time: Utc::now(),
+            message,
+            position,
+        };
+        push_capped(&mut self.deaths, death);
+    }
+}
+
+fn push_capped<T>(list: &mut Vec<T>, item: T) {
+    if list.len() >= MAX_HISTORY {
+        list.remove(0);
+    }
+    list.push(item);
+}
+
+/// Keeps the persisted state in memory and writes it to `state.json` in the
+/// data directory whenever it's saved after a change
+#[derive(Debug)]
+pub struct StateStore {
+    path: PathBuf,
+    state: Mutex<PersistedState>,
+    dirty: AtomicBool,
+}
+
+impl StateStore {
+    /// Loads the state from the data directory, or starts fresh if there is
+    /// none yet
+    pub async fn open(directory: &Path) -> Result<Self> {
+        tokio::fs::create_dir_all(directory)
+            .await
+            .with_context(|| format!("Failed to create {}", directory.display()))?;
+
+        let path = directory.join("state.json");
+        let state = match tokio::fs::read(&path).await {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .with_context(|| format!("{} is broken", path.display()))?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => PersistedState::default(),
+            Err(err) => return Err(err.into()),
+        };
+
+        Ok(Self {
+            path,
+            state: Mutex::new(state),
+            dirty: AtomicBool::new(false),
+        })
+    }
+
+    pub fn get(&self) -> PersistedState {
+        self.state.lock().unwrap().clone()
+    }
+
+    /// Changes the state, which is written to disk on the next save
+    pub fn update(&self, f: impl FnOnce(&mut PersistedState)) {
+        f(&mut self.state.lock().unwrap());
+        self.dirty.store(true, Ordering::Relaxed);
+    }
+
+    /// Writes the state to disk if it changed since the last save
+    pub async fn save(&self) -> Result<()> {
+        if !self.dirty.swap(false, Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        let result = self.write().await;
+        if result.is_err() {
+            // Try again next time
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+        result
+    }
+
+    async fn write(&self) -> Result<()> {
+        let json = serde_json::to_vec_pretty(&self.get())?;
+
+        // Replace the file in one go so a crash can't leave half of it behind
+        let temp = self.path.with_extension("json.tmp");
+        tokio::fs::write(&temp, json).await?;
+        tokio::fs::rename(&temp, &self.path).await?;
+        Ok(())
+    }
+}
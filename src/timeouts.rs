@@ -0,0 +1,62 @@
+use std::{fmt, future::Future, time::Duration};
+use thiserror::Error;
+
+use crate::config::TimeoutsConfig;
+
+/// What we were waiting for a client to send
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// The PROXY protocol header from a load balancer
+    ProxyHeader,
+    Handshake,
+    StatusRequest,
+    PingRequest,
+    LoginHello,
+    EncryptionResponse,
+}
+
+#[derive(Error, Debug)]
+#[error("timed out after {}s waiting for the {phase}", .timeout.as_secs())]
+pub struct TimeoutError {
+    pub phase: Phase,
+    pub timeout: Duration,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Self::ProxyHeader => "PROXY protocol header",
+            Self::Handshake => "handshake",
+            Self::StatusRequest => "status request",
+            Self::PingRequest => "ping request",
+            Self::LoginHello => "login hello",
+            Self::EncryptionResponse => "encryption response",
+        };
+        f.write_str(name)
+    }
+}
+
+impl Phase {
+    /// How long a client gets for this phase
+    pub fn timeout(self, config: &TimeoutsConfig) -> Duration {
+        let seconds = match self {
+            Self::ProxyHeader | Self::Handshake => config.handshake,
+            Self::StatusRequest | Self::PingRequest => config.status,
+            Self::LoginHello => config.login,
+            Self::EncryptionResponse => config.encryption,
+        };
+        Duration::from_secs(seconds)
+    }
+}
+
+/// Gives up on a read that takes longer than its phase allows
+pub async fn timely<T>(
+    config: &TimeoutsConfig,
+    phase: Phase,
+    read: impl Future<Output = T>,
+) -> Result<T, TimeoutError> {
+    let timeout = phase.timeout(config);
+    tokio::time::timeout(timeout, read)
+        .await
+        .map_err(|_| TimeoutError { phase, timeout })
+}